@@ -0,0 +1,54 @@
+//! Compare `Firmware::find_instruction` against a plain linear scan.
+//!
+//! Run with `cargo bench --bench find_instruction`.
+extern crate mimar;
+
+use std::time::{Duration, Instant};
+
+use mimar::firmware::{Firmware, Instruction, InstructionKind, OperandKind};
+
+const LOOKUPS: u32 = 1_000_000;
+
+/// Build a firmware with 40 instructions.
+fn firmware() -> Firmware {
+    let mut firmware = Firmware::new();
+    for i in 0..40u8 {
+        let opcode = if i < 0xF { i } else { 0xF0 + i - 0xF };
+        firmware.insert_instruction(Instruction {
+            opcode: opcode,
+            mnemonic: format!("I{}", i),
+            start: i,
+            kind: InstructionKind::Normal,
+            operand: OperandKind::from_opcode(opcode),
+        });
+    }
+    firmware
+}
+
+fn linear_scan(firmware: &Firmware, opcode: u8) -> Option<&Instruction> {
+    firmware.instructions().iter().find(|i| i.opcode == opcode)
+}
+
+fn measure<F: Fn(u8) -> bool>(lookup: F) -> Duration {
+    let start = Instant::now();
+    let mut found = 0;
+    for i in 0..LOOKUPS {
+        // cycle through the defined opcodes, the last ones are the slowest
+        // for a linear scan
+        let opcode = 0xF0 + (i % 25) as u8;
+        if lookup(opcode) {
+            found += 1;
+        }
+    }
+    assert_eq!(found, LOOKUPS);
+    start.elapsed()
+}
+
+fn main() {
+    let firmware = firmware();
+    let linear = measure(|o| linear_scan(&firmware, o).is_some());
+    let indexed = measure(|o| firmware.find_instruction(o).is_some());
+    println!("{} lookups on a 40-instruction firmware", LOOKUPS);
+    println!("  linear scan:      {:?}", linear);
+    println!("  find_instruction: {:?}", indexed);
+}
@@ -0,0 +1,36 @@
+//! Compare memory lookups in the `BTreeMap` of `Mima` against a `HashMap`.
+//!
+//! Run with `cargo bench --bench memory`.
+extern crate mimar;
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use mimar::Mima;
+
+const LOOKUPS: u32 = 1_000_000;
+const CELLS: u32 = 1000;
+
+fn measure<F: Fn(u32) -> u32>(lookup: F) -> Duration {
+    let start = Instant::now();
+    let mut sum = 0u32;
+    for i in 0..LOOKUPS {
+        sum = sum.wrapping_add(lookup(i % (2 * CELLS)));
+    }
+    assert!(sum > 0);
+    start.elapsed()
+}
+
+fn main() {
+    let mut m = Mima::new();
+    let mut hashed = HashMap::new();
+    for address in 0..CELLS {
+        m.set_memory(address, address + 1);
+        hashed.insert(address, address + 1);
+    }
+    let hashmap = measure(|a| *hashed.get(&a).unwrap_or(&0));
+    let btreemap = measure(|a| m.get_memory(a));
+    println!("{} lookups in a memory with {} cells", LOOKUPS, CELLS);
+    println!("  HashMap:    {:?}", hashmap);
+    println!("  get_memory: {:?}", btreemap);
+}
@@ -0,0 +1,588 @@
+//! Assembler for MIMA programs.
+//!
+//! The assembler turns programs written with the mnemonics of a firmware into
+//! the memory format read by `Mima::load`. See
+//! [`mimar-asm`](../../mimar_asm/index.html) for a description of the syntax.
+
+use std::collections::{HashMap, HashSet};
+use std::error;
+use std::fmt::{self, Display, Formatter, Write};
+use std::io::{self, BufRead};
+
+use regex::Regex;
+
+use super::firmware::Firmware;
+use super::{masks, util};
+
+/// Argument to a command.
+///
+/// Can either be a constant or a global (which might not yet be defined).
+#[derive(Debug, Clone, PartialEq, Hash, Eq)]
+enum Argument {
+    /// Constant defined in the source
+    Constant(i32),
+    /// Placeholder for a global variable, plus an offset
+    Global(String, i32),
+    /// No argument
+    None,
+}
+
+/// Assembler error
+///
+/// Errors in the source contain the 1-based line number.
+#[derive(Debug)]
+pub enum AsmError {
+    /// Invalid input line
+    InvalidLine(usize, String),
+    /// No label with the given name found
+    NoLabel(String),
+    /// Invalid number literal
+    InvalidLiteral(usize, String),
+    /// Invalid command
+    InvalidCommand(String),
+    /// Constant used but never defined
+    UndefinedConstant(usize, String),
+    /// Constant or label defined more than once
+    Redefinition(usize, String),
+    /// The location was moved before address 0
+    NegativeLocation(usize, String),
+    /// The location was moved beyond the address space
+    LocationOutOfRange(usize, String),
+    /// A cell at the given address was already emitted
+    AddressOverlap(usize, u32),
+    /// The alignment is not a power of two
+    InvalidAlignment(usize, String),
+    /// Underlying IO error
+    IoError(io::Error),
+}
+
+impl Display for AsmError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        try!(write!(fmt, "{}: ", error::Error::description(self)));
+        match *self {
+            AsmError::InvalidLine(n, ref l) =>
+                write!(fmt, "line {}: {}", n, l),
+            AsmError::NoLabel(ref l) =>
+                write!(fmt, "{}", l),
+            AsmError::InvalidLiteral(n, ref l) =>
+                write!(fmt, "line {}: {}", n, l),
+            AsmError::InvalidCommand(ref l) =>
+                write!(fmt, "{}", l),
+            AsmError::UndefinedConstant(n, ref c) =>
+                write!(fmt, "line {}: {}", n, c),
+            AsmError::Redefinition(n, ref c) =>
+                write!(fmt, "line {}: {}", n, c),
+            AsmError::NegativeLocation(n, ref l) =>
+                write!(fmt, "line {}: {}", n, l),
+            AsmError::LocationOutOfRange(n, ref l) =>
+                write!(fmt, "line {}: {}", n, l),
+            AsmError::AddressOverlap(n, address) =>
+                write!(fmt, "line {}: {:#07x}", n, address),
+            AsmError::InvalidAlignment(n, ref l) =>
+                write!(fmt, "line {}: {}", n, l),
+            AsmError::IoError(ref e) =>
+                write!(fmt, "{}", e),
+        }
+    }
+}
+
+impl error::Error for AsmError {
+    fn description(&self) -> &str {
+        match *self {
+            AsmError::InvalidLine(..) => "invalid line",
+            AsmError::NoLabel(..) => "unknown label",
+            AsmError::InvalidLiteral(..) => "invalid literal",
+            AsmError::InvalidCommand(..) => "invalid command",
+            AsmError::UndefinedConstant(..) => "undefined constant",
+            AsmError::Redefinition(..) => "redefinition",
+            AsmError::NegativeLocation(..) => "negative location",
+            AsmError::LocationOutOfRange(..) => "location out of range",
+            AsmError::AddressOverlap(..) => "address used twice",
+            AsmError::InvalidAlignment(..) => "alignment is not a power of two",
+            AsmError::IoError(_) => "IO error",
+        }
+    }
+}
+
+impl From<io::Error> for AsmError {
+    fn from(e: io::Error) -> AsmError {
+        AsmError::IoError(e)
+    }
+}
+
+/// Move the location by the given offset.
+///
+/// The new location has to be a valid address, otherwise an error for the
+/// given line is returned.
+fn move_location(location: i32, offset: i32, line_no: usize, line: &str) -> Result<i32, AsmError> {
+    match location.checked_add(offset) {
+        Some(l) if l < 0 => Err(AsmError::NegativeLocation(line_no, line.into())),
+        Some(l) if l as u32 <= masks::ADDRESS_MASK + 1 => Ok(l),
+        _ => Err(AsmError::LocationOutOfRange(line_no, line.into())),
+    }
+}
+
+fn parse_num(input: &str, line_no: usize, line: &str) -> Result<i32, AsmError> {
+    util::parse_num(input).ok_or_else(
+        || AsmError::InvalidLiteral(line_no, line.into()))
+}
+
+/// Parse the argument of a command.
+///
+/// The argument can be a constant, a global or a sum of those, e.g. `LOOP+2`
+/// or `DATA-1`. Names of constants are replaced by their value. At most one
+/// other global is allowed and it can't be subtracted.
+fn parse_argument(arg: &str, constants: &HashMap<String, i32>, line_no: usize, line: &str)
+                  -> Result<Argument, AsmError> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut sign = 1;
+    for chr in arg.chars() {
+        // a leading - belongs to the number
+        if (chr == '+' || chr == '-') && !current.trim().is_empty() {
+            terms.push((sign, current));
+            current = String::new();
+            sign = if chr == '-' { -1 } else { 1 };
+        } else {
+            current.push(chr);
+        }
+    }
+    terms.push((sign, current));
+
+    let mut global = None;
+    let mut offset: i32 = 0;
+    for (sign, term) in terms {
+        let term = term.trim();
+        if let Some(value) = util::parse_num(term).or_else(|| constants.get(term).cloned()) {
+            offset = offset.wrapping_add(sign * value);
+        } else if global.is_none() && sign > 0 && is_identifier(term) {
+            global = Some(term.to_owned());
+        } else {
+            return Err(AsmError::InvalidLiteral(line_no, line.into()));
+        }
+    }
+    Ok(match global {
+        Some(name) => Argument::Global(name, offset),
+        None => Argument::Constant(offset),
+    })
+}
+
+/// Check if the given string is a valid label name.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().map(|c| c.is_alphabetic()).unwrap_or(false) &&
+        chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Define the label with the given name.
+///
+/// Fails if the label is already defined or if there is a constant with the
+/// same name.
+fn define_label(globals: &mut HashMap<String, i32>, constants: &HashMap<String, i32>,
+                name: &str, address: i32, line_no: usize) -> Result<(), AsmError> {
+    if globals.contains_key(name) || constants.contains_key(name) {
+        return Err(AsmError::Redefinition(line_no, name.into()));
+    }
+    globals.insert(name.into(), address);
+    Ok(())
+}
+
+/// Return the position of the comment in the line.
+///
+/// Semicolons inside of string literals don't start a comment.
+fn comment_start(line: &str) -> usize {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, chr) in line.char_indices() {
+        match chr {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return i,
+            _ => (),
+        }
+    }
+    line.len()
+}
+
+/// Parse the values of a `DS` command.
+///
+/// The values are separated by commas and can either be constants or string
+/// literals. A string literal produces one value per byte.
+fn parse_data(values: &str, constants: &HashMap<String, i32>, line_no: usize, line: &str)
+              -> Result<Vec<i32>, AsmError> {
+    let invalid = || AsmError::InvalidCommand(line.into());
+    let mut result = Vec::new();
+    let mut rest = values.trim();
+    loop {
+        if rest.starts_with('"') {
+            let mut chars = rest.char_indices().skip(1);
+            let mut text = String::new();
+            let mut end = None;
+            while let Some((i, chr)) = chars.next() {
+                match chr {
+                    '"' => {
+                        end = Some(i + 1);
+                        break;
+                    },
+                    '\\' => match chars.next() {
+                        Some((_, 'n')) => text.push('\n'),
+                        Some((_, 't')) => text.push('\t'),
+                        Some((_, '\\')) => text.push('\\'),
+                        Some((_, '"')) => text.push('"'),
+                        _ => return Err(invalid()),
+                    },
+                    _ => text.push(chr),
+                }
+            }
+            // unterminated string
+            let end = try!(end.ok_or_else(&invalid));
+            result.extend(text.bytes().map(|b| b as i32));
+            rest = rest[end..].trim();
+        } else {
+            let value_end = rest.find(',').unwrap_or(rest.len());
+            let value = rest[..value_end].trim();
+            if let Some(value) = util::parse_num(value) {
+                result.push(value);
+            } else if let Some(&value) = constants.get(value) {
+                result.push(value);
+            } else if is_identifier(value) {
+                return Err(AsmError::UndefinedConstant(line_no, value.into()));
+            } else {
+                return Err(invalid());
+            }
+            rest = &rest[value_end..];
+        }
+        if rest.is_empty() {
+            return Ok(result);
+        } else if rest.starts_with(',') && rest.len() > 1 {
+            rest = rest[1..].trim();
+        } else {
+            return Err(invalid());
+        }
+    }
+}
+
+/// Number format of the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    /// Hexadecimal with `0x` prefix
+    Hex,
+    /// Decimal
+    Dec,
+}
+
+impl Radix {
+    /// Format a memory cell as `address value`.
+    ///
+    /// Both formats are padded to the same width, so the columns align.
+    fn format_cell(&self, address: u32, value: u32) -> String {
+        match *self {
+            Radix::Hex => format!("{:#07x} {:#08x}", address, value),
+            Radix::Dec => format!("{:07} {:08}", address, value),
+        }
+    }
+}
+
+/// A label reference that has been resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    /// The address of the cell that references the label.
+    pub address: u32,
+    /// The name of the label.
+    pub label: String,
+    /// The offset added to the label, e.g. 2 for `LOOP+2`.
+    pub offset: i32,
+    /// The resulting argument.
+    pub value: u32,
+}
+
+/// Result of the assembler.
+#[derive(Debug, Clone)]
+pub struct Assembly {
+    /// The memory in the output format, see `Mima::load`.
+    pub output: String,
+    /// Listing that shows which source line produced which cells.
+    pub listing: String,
+    /// Table of all labels and constants.
+    pub symbols: String,
+    /// The assembled memory cells.
+    pub memory: HashMap<u32, u32>,
+    /// The labels with their addresses.
+    pub labels: HashMap<String, u32>,
+    /// All label references with the addresses they resolved to, sorted by
+    /// the address of the referencing cell.
+    pub resolutions: Vec<Resolution>,
+    /// Warnings, e.g. about mnemonics that are defined for multiple opcodes.
+    pub warnings: Vec<String>,
+}
+
+/// Assemble the program from the given reader.
+///
+/// Returns the memory in the format read by `Mima::load`, including the
+/// labels.
+///
+/// ```rust
+/// use mimar::asm::assemble;
+/// use mimar::firmware::stock_firmware;
+/// let output = assemble(&stock_firmware(), "START: LDC 5\nHALT\n".as_bytes()).unwrap();
+/// assert_eq!(output, "0x00000 0x000005 ;START\n0x00001 0xf00000\n");
+/// ```
+pub fn assemble<B: BufRead>(fw: &Firmware, input: B) -> Result<String, AsmError> {
+    assemble_full(fw, input, Radix::Hex).map(|asm| asm.output)
+}
+
+/// Assemble the program from the given reader and return the memory cells and
+/// the labels.
+///
+/// ```rust
+/// use mimar::asm::assemble_to_memory;
+/// use mimar::firmware::stock_firmware;
+/// let (memory, labels) = assemble_to_memory(&stock_firmware(), "X: DS 5\nLDV X\n".as_bytes())
+///     .unwrap();
+/// assert_eq!(memory[&0x1], 0x100000);
+/// assert_eq!(labels["X"], 0x0);
+/// ```
+pub fn assemble_to_memory<B: BufRead>(fw: &Firmware, input: B)
+                                      -> Result<(HashMap<u32, u32>, HashMap<String, u32>), AsmError> {
+    assemble_full(fw, input, Radix::Hex).map(|asm| (asm.memory, asm.labels))
+}
+
+/// Assemble the program from the given reader and return all results.
+///
+/// The output uses the given radix, the listing is always hexadecimal.
+pub fn assemble_full<B: BufRead>(fw: &Firmware, input: B, radix: Radix)
+                                 -> Result<Assembly, AsmError> {
+    lazy_static! {
+        static ref SETLOC: Regex = Regex::new(r"^\*\s*=\s*([-+])?\s*([$xbo0-9a-fA-F_]+)$").unwrap();
+        static ref CONSTANT: Regex = Regex::new(r"([A-Za-z]\w*)\s*=\s*([$xbo0-9a-fA-F_]+)$").unwrap();
+        static ref LABEL: Regex = Regex::new(r"([A-Za-z]\w*):$").unwrap();
+        static ref DATA: Regex = Regex::new(
+            r"^(?:(?P<label>[A-Za-z]\w*):)?\s*DS\s+(?P<values>.+)$").unwrap();
+        static ref RESERVE: Regex = Regex::new(
+            r"^(?:(?P<label>[A-Za-z]\w*):)?\s*RESW\s+(?P<count>[$xbo0-9a-fA-F_]+)$").unwrap();
+        static ref ALIGN: Regex = Regex::new(
+            r"^(?:(?P<label>[A-Za-z]\w*):)?\s*ALIGN\s+(?P<alignment>[$xbo0-9a-fA-F_]+)$").unwrap();
+        static ref COMMAND: Regex = Regex::new(
+            r"(?:(?P<label>[A-Za-z]\w*):)?\s*(?P<command>[A-Za-z]+)(?:\s+(?P<arg>-?[$\w]+(?:\s*[-+]\s*[$\w]+)*))?$").unwrap();
+    }
+    let mut result: HashMap<u32, (String, Argument)> = HashMap::new();
+    let mut globals: HashMap<String, i32> = HashMap::new();
+    let mut constants: HashMap<String, i32> = HashMap::new();
+    let lines = try!(input.lines().collect::<Result<Vec<_>, _>>());
+
+    // Collect the constants first, so they can be used before their definition
+    for (line_no, input_line) in lines.iter().enumerate() {
+        let line_no = line_no + 1;
+        let line = input_line[..comment_start(&input_line)].trim();
+        if let Some(cap) = CONSTANT.captures(&line) {
+            if constants.contains_key(&cap[1]) {
+                return Err(AsmError::Redefinition(line_no, cap[1].into()));
+            }
+            let value = try!(parse_num(&cap[2], line_no, line));
+            constants.insert(cap[1].into(), value);
+        }
+    }
+
+    // source lines with the addresses of the cells they produced
+    let mut source: Vec<(usize, String, Vec<u32>)> = Vec::new();
+    let mut next = 0;
+    for (line_no, input_line) in lines.into_iter().enumerate() {
+        let line_no = line_no + 1;
+        let line = &input_line[..comment_start(&input_line)];
+        let line = line.trim();
+        let mut cells = Vec::new();
+        if line.is_empty() {
+            source.push((line_no, input_line, cells));
+            continue;
+        }
+        if let Some(cap) = SETLOC.captures(&line) {
+            let value = try!(parse_num(&cap[2], line_no, line));
+            next = match cap.at(1) {
+                Some("+") => try!(move_location(next, value, line_no, line)),
+                Some(_) => {
+                    let offset = try!(value.checked_neg().ok_or_else(
+                        || AsmError::NegativeLocation(line_no, line.into())));
+                    try!(move_location(next, offset, line_no, line))
+                },
+                None => try!(move_location(0, value, line_no, line)),
+            };
+
+        } else if CONSTANT.is_match(&line) {
+            // already handled
+
+        } else if let Some(cap) = LABEL.captures(&line) {
+            try!(define_label(&mut globals, &constants, &cap[1], next, line_no));
+
+        } else if let Some(cap) = DATA.captures(&line) {
+            if let Some(name) = cap.name("label") {
+                try!(define_label(&mut globals, &constants, name, next, line_no));
+            }
+            let values = try!(parse_data(cap.name("values").unwrap(), &constants, line_no, line));
+            for value in values {
+                if next as u32 > masks::ADDRESS_MASK {
+                    return Err(AsmError::LocationOutOfRange(line_no, line.into()));
+                }
+                if result.insert(next as u32, ("DS".into(), Argument::Constant(value))).is_some() {
+                    return Err(AsmError::AddressOverlap(line_no, next as u32));
+                }
+                cells.push(next as u32);
+                next += 1;
+            }
+
+        } else if let Some(cap) = RESERVE.captures(&line) {
+            if let Some(name) = cap.name("label") {
+                try!(define_label(&mut globals, &constants, name, next, line_no));
+            }
+            let count = try!(parse_num(cap.name("count").unwrap(), line_no, line));
+            next = try!(move_location(next, count, line_no, line));
+
+        } else if let Some(cap) = ALIGN.captures(&line) {
+            let alignment = try!(parse_num(cap.name("alignment").unwrap(), line_no, line));
+            if alignment <= 0 || alignment & (alignment - 1) != 0 {
+                return Err(AsmError::InvalidAlignment(line_no, line.into()));
+            }
+            let aligned = try!(next.checked_add(alignment - 1).ok_or_else(
+                || AsmError::LocationOutOfRange(line_no, line.into()))) & !(alignment - 1);
+            next = try!(move_location(aligned, 0, line_no, line));
+            if let Some(name) = cap.name("label") {
+                try!(define_label(&mut globals, &constants, name, next, line_no));
+            }
+
+        } else if let Some(cap) = COMMAND.captures(&line) {
+            if let Some(name) = cap.name("label") {
+                try!(define_label(&mut globals, &constants, name, next, line_no));
+            }
+            let arg = match cap.name("arg") {
+                Some(v) => try!(parse_argument(v, &constants, line_no, line)),
+                None => Argument::None,
+            };
+            let cmd = cap.name("command").unwrap();
+            if next as u32 > masks::ADDRESS_MASK {
+                return Err(AsmError::LocationOutOfRange(line_no, line.into()));
+            }
+            if result.insert(next as u32, (cmd.into(), arg)).is_some() {
+                return Err(AsmError::AddressOverlap(line_no, next as u32));
+            }
+            cells.push(next as u32);
+            next += 1;
+
+        } else {
+            return Err(AsmError::InvalidLine(line_no, input_line.clone()));
+        }
+        source.push((line_no, input_line, cells));
+    }
+    let mut reverse_labels: HashMap<u32, Vec<&str>> = HashMap::new();
+    for (label, &address) in &globals {
+        reverse_labels.entry(address as u32).or_insert_with(Vec::new).push(label);
+    }
+    // sort the labels, so the output doesn't depend on the hash order
+    for labels in reverse_labels.values_mut() {
+        labels.sort();
+    }
+    let mut memory = result.into_iter().collect::<Vec<_>>();
+    memory.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut output = String::new();
+    let mut words = HashMap::new();
+    let mut ambiguous = HashSet::new();
+    let mut warnings = Vec::new();
+    let mut resolutions = Vec::new();
+    for (address, command) in memory {
+        let mut instr: u32 = 0;
+
+        // Special case DS
+        if command.0 == "DS" {
+            if let Argument::Constant(i) = command.1 {
+                instr = util::from_signed(i, 24);
+            } else {
+                return Err(AsmError::InvalidCommand(command.0));
+            }
+
+        } else {
+            let candidates = fw.instructions_with_mnemonic(&command.0);
+            if candidates.len() > 1 && ambiguous.insert(command.0.to_uppercase()) {
+                let opcodes = candidates.iter()
+                    .map(|i| format!("{:#x}", i.opcode))
+                    .collect::<Vec<_>>();
+                warnings.push(format!("{} is defined for the opcodes {}, using {}",
+                                      command.0, opcodes.join(", "), opcodes[0]));
+            }
+            match fw.find_instruction_by_mnemonic(&command.0) {
+                Some(i) => instr |= i.opcode as u32,
+                None => return Err(AsmError::InvalidCommand(command.0)),
+            }
+            if instr > 0xF {
+                instr <<= masks::EXTENDED_SHIFT;
+            } else {
+                instr <<= masks::OPCODE_SHIFT;
+            }
+            match command.1 {
+                Argument::Constant(i) => instr |= i as u32 & masks::ADDRESS_MASK,
+                Argument::Global(n, offset) => {
+                    if let Some(l) = globals.get(&n) {
+                        let value = l.wrapping_add(offset) as u32 & masks::ADDRESS_MASK;
+                        resolutions.push(Resolution {
+                            address: address,
+                            label: n,
+                            offset: offset,
+                            value: value,
+                        });
+                        instr |= value;
+                    } else {
+                        return Err(AsmError::NoLabel(n));
+                    }
+                },
+                Argument::None => (),
+            }
+        }
+
+        write!(output, "{}", radix.format_cell(address, instr)).unwrap();
+        if let Some(labels) = reverse_labels.get(&address) {
+            write!(output, " ;{}", labels.join(" ")).unwrap();
+        }
+        writeln!(output, "").unwrap();
+        words.insert(address, instr);
+    }
+
+    let mut listing = String::new();
+    for (line_no, text, cells) in source {
+        let mut rows = cells.iter()
+            .map(|adr| format!("{:#07x} {:#08x}", adr, words[adr]))
+            .collect::<Vec<_>>();
+        if rows.is_empty() {
+            rows.push(String::new());
+        }
+        for (i, row) in rows.iter().enumerate() {
+            let row = if i == 0 {
+                format!("{:>5}  {:16}  {}", line_no, row, text)
+            } else {
+                format!("{:>5}  {}", "", row)
+            };
+            writeln!(listing, "{}", row.trim_right()).unwrap();
+        }
+    }
+
+    let mut symbols = String::new();
+    let mut names = globals.keys().chain(constants.keys()).collect::<Vec<_>>();
+    names.sort();
+    for name in names {
+        match globals.get(name) {
+            Some(&address) => writeln!(symbols, "{} = {:#07x}", name, address).unwrap(),
+            None => writeln!(symbols, "{} = {:#08x} # constant", name,
+                             util::from_signed(constants[name], 24)).unwrap(),
+        }
+    }
+
+    let labels = globals.into_iter()
+        .map(|(name, address)| (name, address as u32))
+        .collect();
+    Ok(Assembly {
+        output: output,
+        listing: listing,
+        symbols: symbols,
+        memory: words,
+        labels: labels,
+        resolutions: resolutions,
+        warnings: warnings,
+    })
+}
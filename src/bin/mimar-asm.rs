@@ -10,16 +10,40 @@
 //!
 //! Lines may be prefixed with a label, which will define a global variable with
 //! the command's location as value, e.g. `LOOP: LDV 0`. Instead of constants as
-//! arguments, you can specify labels, like `JMP LOOP`.
+//! arguments, you can specify labels, like `JMP LOOP`. A constant can be added
+//! to or subtracted from a label, like `JMP LOOP+2` or `LDV DATA-1`.
 //!
-//! You can specify where to start blocks with the `*= address`. The
-//! preprocessor can also define constants with `NAME = value`. Note though that
-//! this value is replaced at assemble-time, much like `#define`s in C. The
+//! Numbers can be given in decimal (`10`), hexadecimal (`0xA` or `$A`), binary
+//! (`0b1010`) or octal (`0o12`).
+//!
+//! You can specify where to start blocks with the `*= address`. With `*= +n`
+//! and `*= -n`, the location is moved relative to the current one instead.
+//! Blocks may not overlap, filling a cell twice is an error. Cells can only be
+//! placed in the 20 bit address space, moving the location beyond it is an
+//! error.
+//!
+//! The preprocessor can also define constants with `NAME = value`. Note though
+//! that this value is replaced at assemble-time, much like `#define`s in C. The
 //! value is not placed in the storage and can't be accessed from within the
-//! program.
+//! program. Constants can be used before their definition, both as arguments
+//! and as values for `DS`, but they can't be redefined and can't share their
+//! name with a label.
 //!
 //! To initialize a cell to a value, use the special `DS` instruction. This will
-//! just fill the cell with the given constant.
+//! just fill the cell with the given constant. `DS` also accepts a
+//! comma-separated list of constants (`TABLE: DS 5, 6, 7`), which fills
+//! consecutive cells, starting at the current location. Strings in double
+//! quotes fill one cell per character, so `MSG: DS "Hi\n", 0` defines a
+//! NUL-terminated string. The escapes `\n`, `\t`, `\\` and `\"` are supported.
+//!
+//! To reserve space for an array, use `RESW n`, e.g. `BUF: RESW 10`. This skips
+//! the next `n` cells without emitting them, so the label points to the start
+//! of the reserved region. As all memory is initialized to zero, the reserved
+//! cells are implicitly zero.
+//!
+//! `ALIGN n` moves the location up to the next multiple of `n`, which has to
+//! be a power of two. If the location is already aligned, nothing happens. A
+//! label in front of it (`TABLE: ALIGN 16`) points to the aligned address.
 //!
 //! # Example
 //!
@@ -50,192 +74,36 @@
 //!
 //! The output is a single memory cell per line, in the format `address value`,
 //! where address and value are the hex-encoded address and value. If the
-//! address had a label associated with it, it is placed as a comment after the
-//! line.
+//! address had labels associated with it, they are placed as a comment after
+//! the line, sorted alphabetically and separated by spaces.
+//!
+//! With `--output-radix dec`, address and value are written as zero-padded
+//! decimal numbers instead, for tools that can't read hexadecimal numbers.
+//!
+//! With `-l listing`, a listing is written additionally. It contains every
+//! source line with its line number and the address and value of the cells it
+//! produced. Lines that produce no cells (like labels or constants) have the
+//! address and value columns left blank.
+//!
+//! With `--symbols file`, a symbol table is written additionally. It contains
+//! one line `NAME = value` for each label and constant, sorted by name.
+//! Constants are marked with a `# constant` comment, the other values are the
+//! addresses of labels.
+//!
+//! With `-v`, the assembler logs the address each label reference resolves to
+//! on stderr.
 extern crate mimar;
 extern crate rustc_serialize;
 extern crate docopt;
-#[macro_use]
-extern crate lazy_static;
-extern crate regex;
 
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
+use std::io::{self, BufReader, Write};
 use std::process;
-use std::collections::HashMap;
-use std::fmt::{self, Display, Formatter, Write as FmtWrite};
-use std::error;
 
 use docopt::Docopt;
-use regex::Regex;
 
+use mimar::asm::{self, Radix};
 use mimar::firmware::Firmware;
-use mimar::util;
-use mimar::masks;
-
-/// Argument to a command.
-///
-/// Can either be a constant or a global (which might not yet be defined).
-#[derive(Debug, Clone, PartialEq, Hash, Eq)]
-enum Argument {
-    /// Constant defined in the source
-    Constant(i32),
-    /// Placeholder for a global variable
-    Global(String),
-    /// No argument
-    None,
-}
-
-/// Assembler error
-#[derive(Debug)]
-enum Error {
-    /// Invalid input line
-    InvalidLine(usize, String),
-    /// No label with the given name found
-    NoLabel(String),
-    /// Invalid number literal
-    InvalidLiteral(usize, String),
-    /// Invalid command
-    InvalidCommand(String),
-    /// Underlying IO error
-    IoError(io::Error),
-}
-
-impl Display for Error {
-    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
-        try!(write!(fmt, "{}: ", error::Error::description(self)));
-        match *self {
-            Error::InvalidLine(n, ref l) =>
-                write!(fmt, "line {}: {}", n, l),
-            Error::NoLabel(ref l) =>
-                write!(fmt, "{}", l),
-            Error::InvalidLiteral(n, ref l) =>
-                write!(fmt, "line {}: {}", n, l),
-            Error::InvalidCommand(ref l) =>
-                write!(fmt, "{}", l),
-            Error::IoError(ref e) =>
-                write!(fmt, "{}", e),
-        }
-    }
-}
-
-impl error::Error for Error {
-    fn description(&self) -> &str {
-        match *self {
-            Error::InvalidLine(..) => "invalid line",
-            Error::NoLabel(..) => "unknown label",
-            Error::InvalidLiteral(..) => "invalid literal",
-            Error::InvalidCommand(..) => "invalid command",
-            Error::IoError(_) => "IO error",
-        }
-    }
-}
-
-impl From<io::Error> for Error {
-    fn from(e: io::Error) -> Error {
-        Error::IoError(e)
-    }
-}
-
-fn parse_num(input: &str, line_no: usize, line: &str) -> Result<i32, Error> {
-    util::parse_num(input).ok_or_else(
-        || Error::InvalidLiteral(line_no, line.into()))
-}
-
-/// Assemble input from the given reader, writing to the given writer.
-fn assemble<B: BufRead>(fw: &Firmware, input: B) -> Result<String, Error> {
-    lazy_static! {
-        static ref SETLOC: Regex = Regex::new(r"^\*\s*=\s*([$x0-9a-fA-F]+)$").unwrap();
-        static ref CONSTANT: Regex = Regex::new(r"([A-Za-z]\w*)\s*=\s*([$x0-9a-fA-F]+)$").unwrap();
-        static ref LABEL: Regex = Regex::new(r"([A-Za-z]\w*):$").unwrap();
-        static ref COMMAND: Regex = Regex::new(
-            r"(?:(?P<label>[A-Za-z]\w*):)?\s*(?P<command>[A-Za-z]+)(?:\s+(?P<arg>[-$A-Za-z0-9]+))?$").unwrap();
-    }
-    let mut result: HashMap<u32, (String, Argument)> = HashMap::new();
-    let mut globals: HashMap<String, i32> = HashMap::new();
-    let mut next = 0;
-    for (line_no, input_line) in input.lines().enumerate() {
-        let input_line = try!(input_line);
-        let comment_start = input_line.find(';').unwrap_or(input_line.len());
-        let line = &input_line[..comment_start];
-        let line = line.trim();
-        if line.is_empty() {
-            continue;
-        }
-        if let Some(cap) = SETLOC.captures(&line) {
-            next = try!(parse_num(&cap[1], line_no, line));
-
-        } else if let Some(cap) = CONSTANT.captures(&line) {
-            let value = try!(parse_num(&cap[2], line_no, line));
-            globals.insert(cap[1].into(), value);
-
-        } else if let Some(cap) = LABEL.captures(&line) {
-            globals.insert(cap[1].into(), next as i32);
-
-        } else if let Some(cap) = COMMAND.captures(&line) {
-            if let Some(name) = cap.name("label") {
-                globals.insert(name.into(), next as i32);
-            }
-            let arg = cap.name("arg").map(
-                |v| util::parse_num(v).map(Argument::Constant)
-                    .unwrap_or(Argument::Global(v.into())))
-                .unwrap_or(Argument::None);
-            let cmd = cap.name("command").unwrap();
-            result.insert(next as u32, (cmd.into(), arg));
-            next += 1;
-
-        } else {
-            return Err(Error::InvalidLine(line_no, input_line.clone()));
-        }
-    }
-    let reverse_labels: HashMap<u32, &str> = globals.iter()
-        .map(|(k, v)| (*v as u32, k as &str)).collect();
-    let mut memory = result.into_iter().collect::<Vec<_>>();
-    memory.sort_by(|a, b| a.0.cmp(&b.0));
-    let mut output = String::new();
-    for (address, command) in memory {
-        let mut instr: u32 = 0;
-
-        // Special case DS
-        if command.0 == "DS" {
-            if let Argument::Constant(i) = command.1 {
-                instr = i as u32 & masks::DATA_MASK;
-            } else {
-                return Err(Error::InvalidCommand(command.0));
-            }
-
-        } else {
-            match fw.find_instruction_by_mnemonic(&command.0) {
-                Some(i) => instr |= i.opcode as u32,
-                None => return Err(Error::InvalidCommand(command.0)),
-            }
-            if instr > 0xF {
-                instr <<= masks::EXTENDED_SHIFT;
-            } else {
-                instr <<= masks::OPCODE_SHIFT;
-            }
-            match command.1 {
-                Argument::Constant(i) => instr |= i as u32 & masks::ADDRESS_MASK,
-                Argument::Global(n) => {
-                    if let Some(l) = globals.get(&n) {
-                        println!("GLOBAL {}", n);
-                        instr |= *l as u32 & masks::ADDRESS_MASK;
-                    } else {
-                        return Err(Error::NoLabel(n));
-                    }
-                },
-                Argument::None => (),
-            }
-        }
-
-        write!(output, "{:#07x} {:#08x}", address, instr).unwrap();
-        if let Some(lbl) = reverse_labels.get(&address) {
-            write!(output, " ;{}", lbl).unwrap();
-        }
-        writeln!(output, "").unwrap();
-    }
-    Ok(output)
-}
 
 /// Take the file path and return a `BufReader`.
 ///
@@ -252,12 +120,16 @@ const USAGE: &'static str = "
 MIMA assembler.
 
 Usage:
-  mimar-asm [-o <output>] <firmware> <input>
+  mimar-asm [-v] [-o <output>] [-l <listing>] [--symbols <file>] [--output-radix <radix>] <firmware> <input>
   mimar-asm --help
 
 Options:
-  -h --help      Show this help.
-  -o <output>    Specify the output file [default: out.mima].
+  -h --help               Show this help.
+  -o <output>             Specify the output file [default: out.mima].
+  -l <listing>            Also write a listing to the given file.
+  --symbols <file>        Also write the symbol table to the given file.
+  -v --verbose            Log how labels are resolved to stderr.
+  --output-radix <radix>  Write the output as hex or dec [default: hex].
 ";
 
 #[derive(Debug, RustcDecodable)]
@@ -265,6 +137,10 @@ struct Args {
     arg_firmware: String,
     arg_input: String,
     flag_o: String,
+    flag_l: Option<String>,
+    flag_symbols: Option<String>,
+    flag_output_radix: String,
+    flag_verbose: bool,
 }
 
 fn main() {
@@ -284,14 +160,51 @@ fn main() {
             process::exit(1);
         });
 
-    let asm = assemble(&firmware, input_file(&args.arg_input))
+    let radix = match &args.flag_output_radix as &str {
+        "hex" => Radix::Hex,
+        "dec" => Radix::Dec,
+        other => {
+            println!("Unknown radix {}, expected hex or dec", other);
+            process::exit(1);
+        },
+    };
+
+    let asm = asm::assemble_full(&firmware, input_file(&args.arg_input), radix)
         .unwrap_or_else(|e| {
             println!("Assembler error: {}", e);
             process::exit(1);
         });
 
-    output.write_all(asm.as_bytes()).unwrap_or_else(|e| {
+    for warning in &asm.warnings {
+        writeln!(io::stderr(), "Warning: {}", warning).unwrap();
+    }
+    if args.flag_verbose {
+        for r in &asm.resolutions {
+            writeln!(io::stderr(), "{:#07x}: {} ({:+}) resolves to {:#07x}",
+                     r.address, r.label, r.offset, r.value).unwrap();
+        }
+    }
+
+    output.write_all(asm.output.as_bytes()).unwrap_or_else(|e| {
         println!("Can't write output: {}", e);
         process::exit(1);
     });
+
+    if let Some(ref path) = args.flag_l {
+        File::create(path)
+            .and_then(|mut f| f.write_all(asm.listing.as_bytes()))
+            .unwrap_or_else(|e| {
+                println!("Can't write listing {}: {}", path, e);
+                process::exit(1);
+            });
+    }
+
+    if let Some(ref path) = args.flag_symbols {
+        File::create(path)
+            .and_then(|mut f| f.write_all(asm.symbols.as_bytes()))
+            .unwrap_or_else(|e| {
+                println!("Can't write symbols {}: {}", path, e);
+                process::exit(1);
+            });
+    }
 }
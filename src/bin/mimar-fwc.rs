@@ -11,9 +11,24 @@
 //!
 //! # Input format
 //!
-//! The syntax to define a command is `define MNEMONIC OPCODE`. Mnemonic should
-//! be the human-readable mnemnonic and opcode should be the numeric code,
-//! either decimal (`10`) or hexadecimal (`0x10` or `$10`).
+//! The syntax to define a command is `define MNEMONIC OPCODE [KIND]
+//! [OPERAND]`. Mnemonic should be the human-readable mnemnonic and opcode
+//! should be the numeric code, either decimal (`10`), hexadecimal (`0x10` or
+//! `$10`), binary (`0b10000`) or octal (`0o20`). The optional kind gives the
+//! command a special behaviour that can't be expressed in microcode:
+//!
+//! * `halt`: the MIMA halts when it encounters the command.
+//! * `conditional-neg`: the command jumps to its argument if the accumulator
+//!   is negative.
+//! * `normal`: the command just executes its microcode.
+//!
+//! If the kind is missing, `HALT` and `JMN` get the `halt` and
+//! `conditional-neg` kind, all other commands are `normal`.
+//!
+//! The optional operand tells the tools how to show the argument of the
+//! command: `address`, `constant` or `none`. If it is missing, opcode 0 takes a
+//! constant, the other opcodes up to `0xF` take an address and extended
+//! opcodes take no argument.
 //!
 //! Following the define-line should be the single cycles, so each line defines
 //! which registers are reading and which are writing. The syntax elements are:
@@ -24,7 +39,35 @@
 //!
 //! Multiple operations can be done in a single cycle by separating them with
 //! `;`, but note that you can only have a single source register (as there is
-//! only one bus).
+//! only one bus). The value on the bus can be read by any number of registers,
+//! so `SDR -> X; SDR -> Y` is fine, while `Accu -> X; IR -> Y` is an error.
+//! Reading from the bus without any source, like `-> Accu`, is an error as
+//! well.
+//!
+//! Comments start with `#` or `//` and extend to the end of the line.
+//!
+//! Normally, the cycles are executed one after another and the last cycle of
+//! an instruction jumps back to the fetch phase. A line can be prefixed with a
+//! label (`loop: SDR -> Y`) and a cycle can contain `goto label` to continue
+//! at the labeled cycle instead. The label `fetch` is predefined and points to
+//! the fetch phase. Labels are global, so instructions can share code:
+//!
+//! ```text
+//! define LDV 0x1
+//! IR -> SAR; R = 1
+//! wait: R = 1
+//! R = 1
+//! SDR -> Accu
+//!
+//! define LDVX 0xF3
+//! IR -> SAR; R = 1; goto wait
+//! ```
+//!
+//! For anything the notation can't express, a cycle can be given as a raw
+//! microinstruction word instead, like `raw 0x0A00000`. The word is used
+//! verbatim, except that the address of the next cycle is filled in as usual
+//! if the low 8 bits of the word are 0. A `goto` on the same line still wins.
+//! The word is not validated beyond fitting into 28 bits.
 //!
 //! The ALU operations are:
 //!
@@ -37,6 +80,9 @@
 //! * `eql` or `111`: compare X and Y. If they are equal, the result is -1,
 //!    otherwise 0.
 //!
+//! The names are case-insensitive, and `rotate`, `complement`, `equal`, `cmp`
+//! and `compare` are accepted as aliases.
+//!
 //! But the easiest way is to give a small example:
 //!
 //! ```text
@@ -46,7 +92,7 @@
 //! R = 1
 //! SDR -> Y
 //! ALU 001
-//! Z -> Accu
+//! Z -> Accu  # store the result
 //! ```
 //!
 //! # The default (stock) firmware
@@ -86,16 +132,54 @@
 //! mimar-fwc --default | mimar-fwc -o default.mimafw
 //! ```
 //!
+//! # Multiple input files
+//!
+//! If more than one input file is given, the instructions of the following
+//! files are added to the first one (see `Firmware::merge`). This way, custom
+//! instructions can be kept separate from the default firmware:
+//!
+//! ```bash
+//! mimar-fwc --default -o default-fw.txt
+//! mimar-fwc default-fw.txt custom.txt -o custom.mimafw
+//! ```
+//!
+//! Labels are local to each file, and an opcode may not be defined in more
+//! than one file.
+//!
 //! # Peculiarities
 //!
 //! * The fetch phase is hard-coded and does not need to be defined in the
-//!   input. It's always located at `0x00` in the firmware.
+//!   input. It's always located at `0x00` in the firmware (see below for
+//!   defining it yourself).
 //! * The decode phase is also hard-coded and can be triggered with the
-//!   instruction at `0xFF`.
-//! * The "HALT" command is hardcoded in the MIMA. It's empty in the firmware.
-//!   If the MIMA encounters a command with the mnemonic "HALT", it will halt.
-//! * In similar vein, the "JMN" is hardcoded, because it requires conditional
-//!   execution.
+//!   instruction at `0xFF`, which has the label `decode`.
+//! * The "HALT" command is hardcoded in the MIMA. It's empty in the firmware
+//!   and defined with the `halt` kind. If the MIMA encounters a command with
+//!   this kind, it will halt.
+//! * In similar vein, the "JMN" is hardcoded (with the `conditional-neg`
+//!   kind), because it requires conditional execution.
+//!
+//! # Explicit fetch phase
+//!
+//! With `--explicit-fetch`, the built-in fetch phase is left out. Instead, the
+//! cycles before the first `define` form the fetch phase, which starts at
+//! `0x00` and continues with the decode phase after its last cycle. This allows
+//! experimenting with the fetch phase itself:
+//!
+//! ```text
+//! IAR -> SAR; IAR -> X; R = 1
+//! One -> Y; R = 1
+//! ALU add; R = 1
+//! Z -> IAR
+//! SDR -> IR  # continues with the decode phase
+//!
+//! define LDC 0x0
+//! IR -> Accu
+//! ```
+//!
+//! If more than one input file is given, only the first one contains the fetch
+//! phase. Run such a firmware with `mimar-sim --explicit-fetch`, so that the
+//! simulator doesn't assume that the fetch phase incremented the IAR.
 //!
 //! # Output format
 //!
@@ -126,30 +210,50 @@
 //!
 //! The output has two types of lines:
 //!
-//! Lines in the format `I:MNEMO OPCODE START` build a map of all instructions.
-//! `START` is the first microinstruction in the firmware memory.
+//! Lines in the format `I:MNEMO OPCODE START [KIND]` build a map of all
+//! instructions. `START` is the first microinstruction in the firmware memory
+//! and `KIND` is given for instructions whose kind differs from the one
+//! guessed from the mnemonic. If it is missing, "HALT" and "JMN" get their
+//! special kind and all other instructions are normal.
 //!
 //! Lines in the format `M:ADDRESS VALUE` define the memory of the firmware,
 //! containing all the microinstructions in the format defined above.
+//!
+//! With `--binary`, a more compact binary format is written instead (see
+//! `Firmware::save_binary`). The other tools accept both formats. With
+//! `--compact`, the text format leaves out empty memory cells.
+//!
+//! # Decompiling
+//!
+//! A compiled firmware can be turned back into register transfer notation with
+//! `mimar-fwc --decompile firmware`. The fetch phase is left out, as it is
+//! added by the compiler anyway. Compiling the decompiled firmware yields the
+//! original firmware again.
+//!
+//! # Cycle report
+//!
+//! `mimar-fwc --report firmware` shows how many cycles each instruction of a
+//! compiled firmware takes, including the fetch and decode phase (see
+//! `Firmware::instruction_cycle_count`).
+//!
+//! # Logisim export
+//!
+//! `mimar-fwc --logisim firmware` exports the microcode of a compiled firmware
+//! as Logisim ROM image (see `Firmware::save_logisim`), which can be loaded
+//! into a ROM component with 8 address and 32 data bits.
 extern crate mimar;
-extern crate regex;
-#[macro_use]
-extern crate lazy_static;
 extern crate rustc_serialize;
 extern crate docopt;
 
 use std::io::{self, BufRead, Write, BufReader};
 use std::fs::File;
-use std::error::Error;
-use std::fmt::{self, Display};
+use std::fmt::Write as FmtWrite;
 use std::process;
 
-use regex::Regex;
 use docopt::Docopt;
 
-use mimar::{masks, util};
-use mimar::firmware::{Firmware, Microinstruction, Instruction};
-use mimar::registers::{Register, UnknownRegister};
+use mimar::firmware::Firmware;
+use mimar::fwc;
 
 macro_rules! log {
     ($str:expr, $($args:expr),*) => {
@@ -157,205 +261,34 @@ macro_rules! log {
     }
 }
 
-/// Possible errors that might happen when parsing a register-transfer line.
-#[derive(Debug, PartialEq, Eq)]
-enum RTError {
-    /// Bus already busy because another register is writing data.
-    BusBusy,
-    /// Register name not known.
-    UnknownRegister,
-    /// Register is write only.
-    RegisterReadViolation,
-    /// Register is read only.
-    RegisterWriteViolation,
-    /// Unknown ALU operation
-    InvalidALUInstruction,
-    /// General syntax error
-    SyntaxError,
-}
-
-impl Display for RTError {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, "{}", self.description())
-    }
-}
-
-impl Error for RTError {
-    fn description(&self) -> &'static str {
-        match *self {
-            RTError::BusBusy => "the bus is already being used",
-            RTError::UnknownRegister => "unknown register",
-            RTError::RegisterReadViolation =>
-                "attempting to read a write-only register",
-            RTError::RegisterWriteViolation =>
-                "attempting to write a read-only register",
-            RTError::InvalidALUInstruction =>
-                "invalid ALU instruction",
-            RTError::SyntaxError => "syntax error",
-        }
-    }
-}
-
-impl From<UnknownRegister> for RTError {
-    fn from(_: UnknownRegister) -> Self {
-        RTError::UnknownRegister
-    }
-}
-
-/// Parse a single line of register-transfer-notation.
-fn parse_register_transfer(line: &str) -> Result<Microinstruction, RTError> {
-    lazy_static! {
-        static ref TRANSFER: Regex = Regex::new("^(\\w+)\\s*->\\s*(\\w+)$").unwrap();
-        static ref RW_BIT: Regex = Regex::new("^([RrWw])\\s*=\\s*([10])$").unwrap();
-        static ref ALU: Regex = Regex::new("^ALU ([A-Za-z01]+)$").unwrap();
-    }
-    let parts = line.split(';');
-    let mut source: Option<Register> = None;
-    let mut targets: Vec<Register> = Vec::new();
-    let mut alu = 0;
-    let mut r_bit = 0;
-    let mut w_bit = 0;
-    for part in parts {
-        let part = part.trim();
-        // handle register parts like IAR -> IR
-        if let Some(caps) = TRANSFER.captures(part) {
-            let src_register = try!(caps[1].parse::<Register>());
-            if source.is_some() && source.unwrap() != src_register {
-                return Err(RTError::BusBusy);
-            }
-            source = Some(src_register);
-            let target = try!(caps[2].parse::<Register>());
-            targets.push(target);
-        // handle parts like R=1
-        } else if let Some(caps) = RW_BIT.captures(part) {
-            match &caps[1] {
-                "r" | "R" => r_bit = caps[2].parse().unwrap(),
-                "w" | "W" => w_bit = caps[2].parse().unwrap(),
-                _ => unreachable!(),
-            }
-        // handle parts like ALU add (or ALU 011)
-        } else if let Some(caps) = ALU.captures(part) {
-            let cmd = caps[1].to_lowercase();
-            alu = match &cmd as &str {
-                "noop" | "000" => 0,
-                "add" | "001" => masks::ALU_C0,
-                "rar" | "rotate" | "010" => masks::ALU_C1,
-                "and" | "011" => masks::ALU_C1 | masks::ALU_C0,
-                "or" | "100" => masks::ALU_C2,
-                "xor" | "101" => masks::ALU_C2 | masks::ALU_C0,
-                "not" | "complement" | "110" => masks::ALU_C2 | masks::ALU_C1,
-                "eql" | "equal" | "cmp" | "compare" | "111" =>
-                    masks::ALU_C2 | masks::ALU_C1 | masks::ALU_C0,
-                _ => return Err(RTError::InvalidALUInstruction),
-            }
-        } else {
-            return Err(RTError::SyntaxError);
-        }
-    }
-
-    // build the actual instruction word
-    let mut instr: Microinstruction = 0;
-    if let Some(source) = source {
-        if let (_, Some(write_bit)) = source.control_bits() {
-            instr |= write_bit;
-        } else {
-            return Err(RTError::RegisterReadViolation);
-        }
-        for target in &targets {
-            if let (Some(read_bit), _) = target.control_bits() {
-                instr |= read_bit;
-            } else {
-                return Err(RTError::RegisterWriteViolation);
-            }
-        }
-    }
-    if r_bit == 1 {
-        instr |= masks::MEM_READ;
-    }
-    if w_bit == 1 {
-        instr |= masks::MEM_WRITE;
+/// Compile the given input, exiting on errors.
+fn compile<R: BufRead>(reader: R, explicit_fetch: bool) -> Firmware {
+    let result = if explicit_fetch {
+        fwc::compile_explicit_fetch(reader)
+    } else {
+        fwc::compile(reader)
+    };
+    let firmware = result.unwrap_or_else(|e| {
+        log!("{}", e);
+        process::exit(1);
+    });
+    for instr in firmware.instructions() {
+        log!("Defining {} with Opcode {:#x} (pos {:#x})", instr.mnemonic, instr.opcode, instr.start);
     }
-    Ok(instr | alu)
+    firmware
 }
 
-/// Get the initial fetch phase.
-fn fetch_phase() -> Vec<Microinstruction> {
-    const FETCH_PHASE: &'static str = r#"
-        IAR -> SAR; IAR -> X; R = 1
-        One -> Y; R = 1
-        ALU add; R = 1
-        Z -> IAR
-        SDR -> IR
-    "#;
-    FETCH_PHASE.split("\n")
-        // clean the string
-        .map(str::trim)
-        .filter(|s| !s.is_empty())
-        // parse it
-        .map(|s| parse_register_transfer(s).unwrap())
-        .enumerate()
-        // add the addresses
-        .map(|(i, v)| v | if i == 4 { 0xFF } else { i as u32 + 1 })
-        .collect()
-}
-
-/// Read the data from the given reader and return the compiled firmware.
-fn compile_firmware<R: BufRead>(reader: R) -> Option<Firmware> {
-    lazy_static! {
-        static ref DEFINE: Regex = Regex::new("^define ([A-Z]+) ((?:(?:0x)|$)?[A-Za-z0-9]+)$").unwrap();
-    }
-    let mut firmware = Firmware::new();
-    let mut memory = fetch_phase();
-    for line in reader.lines() {
-        let line = line.unwrap();
-        let line = line.trim();
-        if line.is_empty() {
-            continue;
-        }
-        if let Some(cap) = DEFINE.captures(line) {
-            // finish last instruction
-            if memory.len() > 5 {
-                if let Some(n) = memory.last_mut() {
-                    // wrap back to fetch phase
-                    *n &= masks::MICRO_DATA;
-                }
-            }
-
-            let opcode = util::parse_num(&cap[2]).unwrap() as u8;
-            if firmware.find_instruction(opcode).is_some() {
-                log!("Opcode {:#x} duplicated", opcode);
-                return None;
-            }
-            let pos = memory.len() as u8;
-            let mnemonic = &cap[1];
-            log!("Defining {} with Opcode {:#x} (pos {:#x})", mnemonic, opcode, pos);
-            firmware.insert_instruction(Instruction {
-                opcode: opcode,
-                mnemonic: mnemonic.into(),
-                start: pos,
-            });
-        } else {
-            match parse_register_transfer(&line) {
-                Ok(instr) => {
-                    let next = (memory.len() + 1) as u8;
-                    memory.push(instr | next as u32);
-                },
-                Err(e) => {
-                    log!("{}: {}", e, line);
-                    return None;
-                },
-            }
-        }
-    }
-    // finish last instruction
-    if memory.len() > 5 {
-        if let Some(n) = memory.last_mut() {
-            // wrap back to fetch phase
-            *n &= masks::MICRO_DATA;
-        }
+/// Create a table with the number of cycles of each instruction.
+fn cycle_report(firmware: &Firmware) -> String {
+    let mut output = String::new();
+    writeln!(output, "{:>8} {:>6} {:>6}", "Mnemonic", "Opcode", "Cycles").unwrap();
+    for instr in firmware.instructions() {
+        let cycles = firmware.instruction_cycle_count(instr.opcode)
+            .map(|c| c.to_string())
+            .unwrap_or("endless".into());
+        writeln!(output, "{:>8} {:>#6x} {:>6}", instr.mnemonic, instr.opcode, cycles).unwrap();
     }
-    firmware.load_memory(&memory);
-    Some(firmware)
+    output
 }
 
 static DEFAULT_FW: &'static [u8] = include_bytes!("../default-fw.txt");
@@ -366,21 +299,39 @@ MIMA firmware compiler.
 Takes firmware in register transfer notation and outputs the compiled firmware.
 
 Usage:
-  mimar-fwc [<input>] [-o <output>]
+  mimar-fwc [<input>...] [-o <output>] [--binary | --compact] [--explicit-fetch]
   mimar-fwc --default [-o <output>]
+  mimar-fwc --decompile <firmware> [-o <output>]
+  mimar-fwc --logisim <firmware> [-o <output>]
+  mimar-fwc --report <firmware>
   mimar-fwc --help
 
 Options:
-  -h --help    Show this screen.
-  -o <output>  Set the output file.
-  --default    Output the default firmware.
+  -h --help         Show this screen.
+  -o <output>       Set the output file.
+  --default         Output the default firmware.
+  --binary          Output the compiled firmware in the binary format.
+  --compact         Leave out empty memory cells in the text format.
+  --explicit-fetch  Take the fetch phase from the input instead of adding
+                    the built-in one.
+  --decompile       Turn a compiled firmware back into register transfer
+                    notation.
+  --logisim         Export a compiled firmware as Logisim ROM image.
+  --report          Show the number of cycles each instruction takes.
 ";
 
 #[derive(Debug, RustcDecodable)]
 struct Args {
-    arg_input: Option<String>,
+    arg_input: Vec<String>,
+    arg_firmware: Option<String>,
     flag_o: Option<String>,
     flag_default: bool,
+    flag_decompile: bool,
+    flag_logisim: bool,
+    flag_report: bool,
+    flag_binary: bool,
+    flag_compact: bool,
+    flag_explicit_fetch: bool,
 }
 
 fn arg_to_writer(arg: Option<&String>) -> Box<Write> {
@@ -406,23 +357,69 @@ fn main() {
         return;
     }
 
-    let stdin = io::stdin();
-    let firmware = match args.arg_input {
-        None => compile_firmware(&mut stdin.lock()),
-        Some(ref filename) => {
-            let file = File::open(filename).unwrap_or_else(|e| {
-                log!("Can't open input file {}: {}", filename, e);
-                process::exit(1);
-            });
-            let mut buffered_file = BufReader::new(file);
-            compile_firmware(&mut buffered_file)
+    if args.flag_decompile || args.flag_logisim || args.flag_report {
+        let filename = args.arg_firmware.as_ref().unwrap();
+        let file = File::open(filename).unwrap_or_else(|e| {
+            log!("Can't open firmware {}: {}", filename, e);
+            process::exit(1);
+        });
+        let firmware = Firmware::load(BufReader::new(file)).unwrap_or_else(|e| {
+            log!("Can't load firmware {}: {}", filename, e);
+            process::exit(1);
+        });
+        let mut out = arg_to_writer(args.flag_o.as_ref());
+        if args.flag_report {
+            out.write_all(cycle_report(&firmware).as_bytes()).unwrap();
+        } else if args.flag_logisim {
+            firmware.save_logisim(&mut out).unwrap();
+        } else {
+            out.write_all(fwc::decompile_firmware(&firmware).as_bytes()).unwrap();
         }
-    };
+        return;
+    }
 
-    firmware.unwrap_or_else(|| process::exit(1))
-        .save(&mut arg_to_writer(args.flag_o.as_ref()))
-        .unwrap_or_else(|e| {
-            log!("Can't save firmware: {}", e);
+    let stdin = io::stdin();
+    let mut firmware: Option<Firmware> = None;
+    if args.arg_input.is_empty() {
+        firmware = Some(compile(stdin.lock(), args.flag_explicit_fetch));
+    }
+    for filename in &args.arg_input {
+        let file = File::open(filename).unwrap_or_else(|e| {
+            log!("Can't open input file {}: {}", filename, e);
             process::exit(1);
         });
+        // only the first file defines the fetch phase
+        let explicit_fetch = args.flag_explicit_fetch && firmware.is_none();
+        let compiled = compile(BufReader::new(file), explicit_fetch);
+        match firmware {
+            None => firmware = Some(compiled),
+            Some(ref mut firmware) => {
+                firmware.merge(&compiled, None).unwrap_or_else(|e| {
+                    log!("Can't merge {}: {}", filename, e);
+                    process::exit(1);
+                });
+            },
+        }
+    }
+
+    let firmware = firmware.unwrap_or_else(|| process::exit(1));
+    if let Err(errors) = firmware.validate() {
+        for error in errors {
+            log!("Invalid firmware: {}", error);
+        }
+        process::exit(1);
+    }
+
+    let mut out = arg_to_writer(args.flag_o.as_ref());
+    let result = if args.flag_binary {
+        firmware.save_binary(&mut out)
+    } else if args.flag_compact {
+        firmware.save_compact(&mut out)
+    } else {
+        firmware.save(&mut out)
+    };
+    result.unwrap_or_else(|e| {
+        log!("Can't save firmware: {}", e);
+        process::exit(1);
+    });
 }
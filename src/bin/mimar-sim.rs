@@ -4,18 +4,115 @@
 //! firmware with [`mimar-fwc`](../mimar_fwc/index.html) and assemble programs
 //! with [`mimar-asm`](../mimar_asm/index.html).
 //!
-//! The default firmware is compatible with the output of cbdevnet's
-//! [mimasm](https://github.com/cbdevnet/mima).
+//! The default firmware uses the same opcodes as cbdevnet's
+//! [mimasm](https://github.com/cbdevnet/mima), but only programs in the output
+//! format of `mimar-asm` can be loaded, not the memory dumps of `mimasm`.
 //!
 //! The simulator will simulate the program starting at the given address
-//! (defaulting to 0). At the end, all named cells are output.
+//! (defaulting to 0). At the end, all named cells are output, both as hex and as
+//! signed decimal number. With `--dump-regs`, the final values of the registers
+//! are output as well.
+//!
+//! The whole memory can be saved with `--dump-memory <file>`. The file uses the
+//! same format as the output of [`mimar-asm`](../mimar_asm/index.html), so it
+//! can be used as input for another simulation.
+//!
+//! With `--json`, the trace and the final output are replaced by a single JSON
+//! object, containing the `cycle_count`, the `memory_stall_cycles`, the final
+//! `state`, the values of all labeled `cells` and the `registers`.
+//!
+//! # Profiling
+//!
+//! With `--profile`, the trace is replaced by a table that shows how often each
+//! instruction was executed and how many cycles it took in total. The cycles
+//! are counted from the decoding of an instruction to the decoding of the next
+//! one, so the fetch phase of the next instruction is included. Below the
+//! table, the number of memory stalls is shown: cycles that did nothing but
+//! wait for a memory access to complete.
 //!
 //! # Temporary memory changes
 //!
 //! You can modify single memory cells before the program starts. This is useful
 //! e.g. for input. Use the `-m` parameter for this, with the syntax `-m
 //! target=value`, where `target` can be an address (either decimal or
-//! hexadecimal) or a label. You can give multiple `-m` options.
+//! hexadecimal) or a label. The value can be a (possibly negative) number,
+//! which is truncated to 24 bits, or a label, in which case the cell is set to
+//! the label's address. You can give multiple `-m` options.
+//!
+//! # Symbols
+//!
+//! The labels are taken from the comments in the program. With `--symbols
+//! <file>`, additional labels are loaded from a symbol table as written by
+//! `mimar-asm --symbols`, so they can be used for `-s` and `-m` and show up in
+//! the output. Constants in the symbol table are ignored, and labels from the
+//! program take precedence.
+//!
+//! # Trace
+//!
+//! With `--trace <file>`, a trace of the executed instructions is written to
+//! the given file, in addition to the normal output. The file starts with a
+//! header line, followed by one tab-separated line per instruction with the
+//! cycle in which it was decoded, its address, the opcode, the mnemonic, the
+//! operand (empty for instructions without one) and the value of the Accu
+//! after the instruction:
+//!
+//! ```text
+//! cycle	iar	opcode	mnemonic	operand	accu
+//! 6	0x00000	0x01	LDV	0x00010	0x000005
+//! ```
+//!
+//! # Output
+//!
+//! With `--output <loc>`, writes to the given cell are treated as output: the
+//! low byte of the written value is printed as character.
+//!
+//! Similarly, `--stdin <loc>` maps the given cell to the standard input: each
+//! read returns the next byte of the input, or 0 once the input is exhausted.
+//! This can't be combined with the interactive mode.
+//!
+//! # Self-modifying code
+//!
+//! Writing to cells that contain code is a valid technique, but often a bug.
+//! With `--warn-smc`, a warning is printed whenever a cell is written that has
+//! been executed as an instruction before.
+//!
+//! # End of the program
+//!
+//! A program that doesn't halt runs into the empty cells after it, which are
+//! executed as `LDC 0`. With `--trap-end`, the simulator stops with the state
+//! `EndOfProgram` instead, once it fetches an instruction from an empty cell
+//! after the highest address of the program.
+//!
+//! # Custom fetch phase
+//!
+//! Firmwares compiled with `mimar-fwc --explicit-fetch` define their own fetch
+//! phase. Use `--explicit-fetch` for them, so that the address of an
+//! instruction is taken from the IAR at the start of the fetch phase.
+//!
+//! # Exit code
+//!
+//! With `--exit-cell <loc>`, the simulator exits with the low byte of the given
+//! cell once the MIMA halted. This allows scripts to check whether a program
+//! succeeded. Without this option, or if the MIMA stopped for another reason,
+//! the exit code is unchanged.
+//!
+//! # Interactive mode
+//!
+//! With `-i`, the simulator pauses before each instruction and reads commands
+//! from the standard input:
+//!
+//! * `s`: execute a single instruction
+//! * `c`: continue until the MIMA stops
+//! * `r`: show the registers, the pending memory access and the last value on
+//!   the bus
+//! * `m <target>`: show the memory cell at `target`, which can be an address
+//!   or a label
+//! * `b`: step back to the start of the previous instruction
+//! * `q`: quit
+//!
+//! Stepping back reverts the registers and the memory, but output that has
+//! already been printed stays. The simulator remembers the last 10000 cycles
+//! for this, which can be changed with `--history <cycles>`.
 //!
 //! # Example usage
 //!
@@ -28,22 +125,29 @@
 //! mimar-sim -s START default.mimafw program.mima
 //! # pass a parameter to the label NUMBER
 //! mimar-sim -m NUMBER=10 default.mimafw program.mima
+//! # let the cell PTR point to the cell TARGET
+//! mimar-sim -m PTR=TARGET default.mimafw program.mima
+//! # step through the program
+//! mimar-sim -i default.mimafw program.mima
 //! ```
 extern crate mimar;
 extern crate rustc_serialize;
 extern crate docopt;
 
-use std::io::BufReader;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write as IoWrite};
 use std::fs::File;
 use std::process;
 use std::fmt::Write;
 
 use docopt::Docopt;
+use rustc_serialize::json::Json;
 
-use mimar::{Mima, MimaState};
-use mimar::firmware::{Instruction, Firmware};
-use mimar::logger::Logger;
-use mimar::util;
+use mimar::{Mima, MimaState, masks, util};
+use mimar::firmware::{Instruction, Firmware, OperandKind};
+use mimar::logger::{CompositeLogger, Logger};
+use mimar::registers::Register;
 
 struct ConsoleLogger;
 
@@ -53,8 +157,8 @@ impl Logger for ConsoleLogger {
         let label = labels.next().unwrap_or("");
         let mut param_label = String::new();
         let mut param_labels = m.find_labels(param).into_iter();
-        // only show the label if it's not LDC and not an extended instruction
-        if instr.opcode > 0 && instr.opcode <= 0xF {
+        // only show the label if the argument is an address
+        if instr.operand == OperandKind::Address {
             if let Some(label) = param_labels.next() {
                 write!(param_label, " ({})", label).unwrap();
             }
@@ -63,13 +167,141 @@ impl Logger for ConsoleLogger {
                  m.cycle_count, iar, label, instr.opcode, instr.mnemonic,
                  param, param_label);
     }
+
+    fn log_output(&self, byte: u8) {
+        println!("Output: {:?}", byte as char);
+    }
+
+    fn log_warning(&self, m: &Mima, message: &str) {
+        println!("{:>6} Warning: {}", m.cycle_count, message);
+    }
+}
+
+/// Logger that counts the executions and cycles of each instruction.
+struct Profiler {
+    /// Number of executions and cycles per mnemonic
+    stats: RefCell<HashMap<String, (u64, u64)>>,
+    /// The currently executed instruction and the cycle in which it started
+    current: RefCell<Option<(String, u64)>>,
+}
+
+impl Profiler {
+    fn new() -> Profiler {
+        Profiler {
+            stats: RefCell::new(HashMap::new()),
+            current: RefCell::new(None),
+        }
+    }
+
+    /// Attribute the cycles up to `cycle_count` to the current instruction.
+    fn finish_current(&self, cycle_count: u64) {
+        if let Some((mnemonic, start)) = self.current.borrow_mut().take() {
+            let mut stats = self.stats.borrow_mut();
+            let entry = stats.entry(mnemonic).or_insert((0, 0));
+            entry.1 += cycle_count - start;
+        }
+    }
+
+    /// Print the statistics, sorted by the number of cycles, followed by the
+    /// share of memory stalls.
+    fn print(&self, m: &Mima) {
+        let cycle_count = m.cycle_count;
+        self.finish_current(cycle_count);
+        let stats = self.stats.borrow();
+        let total = stats.values().map(|&(_, cycles)| cycles).sum::<u64>();
+        let mut stats = stats.iter().collect::<Vec<_>>();
+        stats.sort_by(|a, b| (b.1).1.cmp(&(a.1).1).then(a.0.cmp(b.0)));
+        println!("{:>8} {:>10} {:>10} {:>7}", "Mnemonic", "Count", "Cycles", "%");
+        for (mnemonic, &(count, cycles)) in stats {
+            let percentage = if total > 0 { 100. * cycles as f64 / total as f64 } else { 0. };
+            println!("{:>8} {:>10} {:>10} {:>6.2}%", mnemonic, count, cycles, percentage);
+        }
+        println!("{} of {} cycles were memory stalls", m.memory_stall_cycles(), cycle_count);
+    }
+}
+
+impl Logger for Profiler {
+    fn log_instruction(&self, m: &Mima, _: u32, instr: &Instruction, _: u32) {
+        self.finish_current(m.cycle_count);
+        self.stats.borrow_mut().entry(instr.mnemonic.clone()).or_insert((0, 0)).0 += 1;
+        *self.current.borrow_mut() = Some((instr.mnemonic.clone(), m.cycle_count));
+    }
+}
+
+/// Logger that writes one tab-separated line per executed instruction.
+///
+/// The value of the Accu after the instruction is only known once the next
+/// instruction is decoded, so each line is held back until then. Call `finish`
+/// after the simulation to write the last line.
+struct TraceLogger {
+    out: Option<RefCell<BufWriter<File>>>,
+    /// Cycle, IAR, opcode, mnemonic and operand of the current instruction
+    current: RefCell<Option<String>>,
+}
+
+impl TraceLogger {
+    /// Create a logger that writes to the given file, or does nothing if no
+    /// file is given.
+    fn new(filename: Option<&str>) -> TraceLogger {
+        let out = filename.map(|name| {
+            let file = File::create(name).unwrap_or_else(|e| {
+                println!("Can't create {}: {}", name, e);
+                process::exit(1);
+            });
+            let mut out = BufWriter::new(file);
+            TraceLogger::check(writeln!(out, "cycle\tiar\topcode\tmnemonic\toperand\taccu"));
+            RefCell::new(out)
+        });
+        TraceLogger {
+            out: out,
+            current: RefCell::new(None),
+        }
+    }
+
+    fn check(result: io::Result<()>) {
+        result.unwrap_or_else(|e| {
+            println!("Can't write the trace: {}", e);
+            process::exit(1);
+        });
+    }
+
+    /// Write the current instruction with the current value of the Accu.
+    fn write_current(&self, m: &Mima) {
+        if let (Some(out), Some(line)) = (self.out.as_ref(), self.current.borrow_mut().take()) {
+            TraceLogger::check(writeln!(out.borrow_mut(), "{}\t{:#08x}", line, m.accu()));
+        }
+    }
+
+    /// Write the last instruction and flush the file.
+    fn finish(&self, m: &Mima) {
+        self.write_current(m);
+        if let Some(ref out) = self.out {
+            TraceLogger::check(out.borrow_mut().flush());
+        }
+    }
+}
+
+impl Logger for TraceLogger {
+    fn log_instruction(&self, m: &Mima, iar: u32, instr: &Instruction, param: u32) {
+        if self.out.is_none() {
+            return;
+        }
+        self.write_current(m);
+        let operand = match instr.operand {
+            OperandKind::None => String::new(),
+            _ => format!("{:#07x}", param),
+        };
+        *self.current.borrow_mut() = Some(format!("{}\t{:#07x}\t{:#04x}\t{}\t{}",
+                                                  m.cycle_count, iar, instr.opcode,
+                                                  instr.mnemonic, operand));
+    }
 }
 
 const USAGE: &'static str = "
 MIMA simulator.
 
 Usage:
-  mimar-sim [-s <loc>] [-m <memstr>]... <firmware> <input>
+  mimar-sim [-i] [--dump-regs] [--dump-memory <file>] [--json] [--profile] [--output <loc>] [--stdin <loc>] [--warn-smc] [--trap-end] [--explicit-fetch] [--exit-cell <loc>] [--symbols <file>] [--history <cycles>] [--trace <file>] [-s <loc>] [-m <memstr>]... <firmware> <input>
   mimar-sim -h | --help
 
 Options:
@@ -79,6 +311,27 @@ Options:
   -m <memstr>               Set a memory location. memstr should look like
                             address=value, where address can be a label. Can
                             be specified multiple times.
+  -i, --interactive         Pause after each instruction and read commands
+                            from stdin.
+  --dump-regs               Print the registers after the simulation.
+  --dump-memory <file>      Write the memory to the file after the simulation.
+  --json                    Output the result as JSON.
+  --profile                 Print the number of executions and cycles per
+                            instruction.
+  --output <loc>            Print values written to loc (a number or label) as
+                            characters.
+  --stdin <loc>             Let reads from loc (a number or label) return the
+                            bytes from stdin.
+  --warn-smc                Warn when executed code is overwritten.
+  --trap-end                Stop when executing empty cells after the program.
+  --explicit-fetch          The firmware defines its own fetch phase.
+  --exit-cell <loc>         Use the low byte of loc (a number or label) as exit
+                            code once the MIMA halted.
+  --symbols <file>          Load additional labels from a symbol table.
+  --history <cycles>        Number of cycles that can be stepped back in
+                            interactive mode [default: 10000].
+  --trace <file>            Write a tab-separated trace of the executed
+                            instructions to the file.
   -h --help                 Show this screen.
 ";
 
@@ -86,6 +339,20 @@ Options:
 struct Args {
     flag_start: Option<String>,
     flag_m: Vec<String>,
+    flag_interactive: bool,
+    flag_dump_regs: bool,
+    flag_dump_memory: Option<String>,
+    flag_json: bool,
+    flag_profile: bool,
+    flag_output: Option<String>,
+    flag_stdin: Option<String>,
+    flag_warn_smc: bool,
+    flag_trap_end: bool,
+    flag_explicit_fetch: bool,
+    flag_exit_cell: Option<String>,
+    flag_symbols: Option<String>,
+    flag_history: usize,
+    flag_trace: Option<String>,
     arg_firmware: String,
     arg_input: String,
 }
@@ -98,6 +365,140 @@ fn file_input(name: &str) -> BufReader<File> {
     BufReader::new(f)
 }
 
+/// Add the labels of a symbol table written by `mimar-asm --symbols`.
+///
+/// Constants are skipped, as they don't name a cell. Labels that are already
+/// defined are kept. On error, the number of the offending line is returned.
+fn load_symbols<R: BufRead>(m: &mut Mima, reader: R) -> Result<(), usize> {
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = try!(line.map_err(|_| line_no + 1));
+        if line.contains("# constant") {
+            continue;
+        }
+        let line = line[..line.find('#').unwrap_or(line.len())].trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (name, value) = try!(util::split_assignment(line).ok_or(line_no + 1));
+        let address = try!(util::parse_num(value).ok_or(line_no + 1));
+        m.labels.entry(name.into()).or_insert(address as u32);
+    }
+    Ok(())
+}
+
+/// Run the MIMA until it stops.
+fn run<L: Logger>(m: &mut Mima, log: &L) -> MimaState {
+    loop {
+        let state = m.cycle(log);
+        if state != MimaState::Running {
+            return state;
+        }
+    }
+}
+
+/// Print the values of all registers, both as hex and as signed number.
+fn print_registers(m: &Mima) {
+    for register in Register::all() {
+        let value = m.get_register(*register);
+        let digits = (register.width() as usize + 3) / 4;
+        println!("  {:>4}: {:#0width$x} (signed {})",
+                 register.to_string(), value, util::to_signed(value, register.width()),
+                 width = digits + 2);
+    }
+}
+
+/// Write all nonzero and labeled cells in the format read by `Mima::load`.
+fn dump_memory<W: IoWrite>(m: &Mima, output: &mut W) -> io::Result<()> {
+    let mut addresses = m.memory.keys().chain(m.labels.values()).cloned().collect::<Vec<_>>();
+    addresses.sort();
+    addresses.dedup();
+    for address in addresses {
+        try!(write!(output, "{:#07x} {:#08x}", address, m.get_memory(address)));
+        let mut labels = m.find_labels(address);
+        if !labels.is_empty() {
+            labels.sort();
+            try!(write!(output, " ;{}", labels.join(" ")));
+        }
+        try!(writeln!(output, ""));
+    }
+    Ok(())
+}
+
+/// Format a labeled cell for the final output.
+///
+/// The value is shown as hex and as signed decimal, as the MIMA arithmetic is
+/// signed.
+fn format_cell(label: &str, address: u32, data: u32) -> String {
+    format!("  Cell {:#08x} {:>10}: {}", address, label, util::format_signed(data, 24))
+}
+
+/// Collect the result of the simulation in a JSON object.
+fn json_summary(m: &Mima, state: &MimaState) -> Json {
+    let mut cells = BTreeMap::new();
+    for (label, &address) in &m.labels {
+        cells.insert(label.clone(), Json::U64(m.get_memory(address) as u64));
+    }
+    let mut registers = BTreeMap::new();
+    for register in Register::all() {
+        registers.insert(register.to_string(), Json::U64(m.get_register(*register) as u64));
+    }
+    let mut result = BTreeMap::new();
+    result.insert("cycle_count".into(), Json::U64(m.cycle_count));
+    result.insert("memory_stall_cycles".into(), Json::U64(m.memory_stall_cycles()));
+    result.insert("state".into(), Json::String(format!("{:?}", state)));
+    result.insert("cells".into(), Json::Object(cells));
+    result.insert("registers".into(), Json::Object(registers));
+    Json::Object(result)
+}
+
+/// Execute the MIMA step by step, controlled by commands from stdin.
+fn interactive<L: Logger>(m: &mut Mima, log: &L) -> MimaState {
+    let stdin = io::stdin();
+    let mut state = MimaState::Running;
+    print!("> ");
+    io::stdout().flush().unwrap();
+    for line in stdin.lock().lines() {
+        let line = line.unwrap_or_else(|e| {
+            println!("Can't read command: {}", e);
+            process::exit(1);
+        });
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("s") => state = m.step_instruction(log),
+            Some("c") => state = run(m, log),
+            Some("r") => {
+                print_registers(m);
+                if m.memory_pending() {
+                    println!("  Memory access, {} cycles remaining", m.memory_timer());
+                }
+                if let Some(value) = m.last_bus_value() {
+                    println!("  Bus: {:#08x}", value);
+                }
+            },
+            Some("m") => match words.next().and_then(|t| m.resolve(t)) {
+                Some(address) => {
+                    let data = m.get_memory(address);
+                    println!("  Cell {:#08x}: {} {}",
+                             address, util::format_signed(data, 24), m.disassemble(data));
+                },
+                None => println!("Expected an address or label"),
+            },
+            Some("b") => if !m.step_back_instruction() {
+                println!("Can't step back any further");
+            },
+            Some("q") => break,
+            Some(command) => println!("Unknown command {}", command),
+            None => (),
+        }
+        if state != MimaState::Running {
+            break;
+        }
+        print!("> ");
+        io::stdout().flush().unwrap();
+    }
+    state
+}
+
 fn main() {
     let args: Args = Docopt::new(USAGE)
         .and_then(|d| d.decode())
@@ -114,30 +515,33 @@ fn main() {
         process::exit(1);
     });
 
+    if let Some(ref filename) = args.flag_symbols {
+        load_symbols(&mut m, file_input(filename)).unwrap_or_else(|line_no| {
+            println!("Error loading the symbols: invalid line {}", line_no);
+            process::exit(1);
+        });
+    }
+
     for memset in &args.flag_m {
-        let mut split = memset.split("=");
-        let target = split.next().unwrap();
-        let target = util::parse_num(target)
-            .map(|v| v as u32)
-            .or_else(|| m.labels.get(target).map(|v| *v as u32))
+        let (target, value) = util::split_assignment(memset).unwrap_or_else(|| {
+            println!("Malformed memory setting {}, expected address=value", memset);
+            process::exit(1);
+        });
+        let target = m.resolve(target)
             .unwrap_or_else(|| {
                 println!("Can't find cell {}", target);
                 process::exit(1);
             });
-        let value = util::parse_num(split.next().unwrap_or_else(|| {
-            println!("Expected a value");
-            process::exit(1);
-        })).unwrap_or_else(|| {
-            println!("Malformed value");
-            process::exit(1);
-        });
-        m.memory.insert(target, value as u32);
+        let value = m.resolve(value)
+            .unwrap_or_else(|| {
+                println!("Malformed value {}", value);
+                process::exit(1);
+            });
+        m.set_memory(target, value & masks::DATA_MASK);
     }
 
     if let Some(start) = args.flag_start {
-        let num = util::parse_num(&start)
-            .map(|v| v as u32)
-            .or_else(|| m.labels.get(&start).map(|v| *v as u32))
+        let num = m.resolve(&start)
             .unwrap_or_else(|| {
                 println!("Can't find start {}", start);
                 process::exit(1);
@@ -145,19 +549,93 @@ fn main() {
         m.jump(num);
     }
 
-    loop {
-        let state = m.cycle(&ConsoleLogger);
-        if state != MimaState::Running {
-            println!("{:?}", state);
-            break;
+    m.warn_smc = args.flag_warn_smc;
+    m.trap_end_of_program = args.flag_trap_end;
+    m.explicit_fetch = args.flag_explicit_fetch;
+
+    if let Some(output) = args.flag_output {
+        let address = m.resolve(&output)
+            .unwrap_or_else(|| {
+                println!("Can't find output {}", output);
+                process::exit(1);
+            });
+        m.set_output_address(address);
+    }
+
+    if let Some(input) = args.flag_stdin {
+        if args.flag_interactive {
+            println!("--stdin can't be used in interactive mode");
+            process::exit(1);
+        }
+        let address = m.resolve(&input)
+            .unwrap_or_else(|| {
+                println!("Can't find input {}", input);
+                process::exit(1);
+            });
+        m.set_input_address(address);
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data).unwrap_or_else(|e| {
+            println!("Can't read input: {}", e);
+            process::exit(1);
+        });
+        for byte in data {
+            m.push_input(byte as u32);
         }
+    }
+
+    let exit_cell = args.flag_exit_cell.map(|cell| {
+        m.resolve(&cell).unwrap_or_else(|| {
+            println!("Can't find exit cell {}", cell);
+            process::exit(1);
+        })
+    });
+
+    let profiler = Profiler::new();
+    let trace = TraceLogger::new(args.flag_trace.as_ref().map(|f| f as &str));
+    let state = if args.flag_interactive {
+        m.history_depth = args.flag_history;
+        interactive(&mut m, &CompositeLogger::new(&ConsoleLogger, &trace))
+    } else if args.flag_profile {
+        run(&mut m, &CompositeLogger::new(&profiler, &trace))
+    } else if args.flag_json {
+        run(&mut m, &trace)
+    } else {
+        run(&mut m, &CompositeLogger::new(&ConsoleLogger, &trace))
     };
+    trace.finish(&m);
+
+    if args.flag_json {
+        println!("{}", json_summary(&m, &state).pretty());
+    } else {
+        println!("{:?}", state);
+
+        for (label, address) in m.labels_sorted() {
+            println!("{}", format_cell(label, address, m.get_memory(address)));
+        }
+
+        if args.flag_dump_regs {
+            println!("Registers:");
+            print_registers(&m);
+        }
 
-    let mut labels = m.labels.iter().collect::<Vec<_>>();
-    labels.sort_by_key(|&(_, adr)| *adr);
-    for (label, address) in labels {
-        let data = m.get_memory(*address);
-        println!("  Cell {:#08x} {:>10}: {:#8x} ({})",
-                 address, label, data, data);
+        if args.flag_profile {
+            println!("Profile:");
+            profiler.print(&m);
+        }
+    }
+
+    if let Some(filename) = args.flag_dump_memory {
+        File::create(&filename)
+            .and_then(|mut f| dump_memory(&m, &mut f))
+            .unwrap_or_else(|e| {
+                println!("Can't write {}: {}", filename, e);
+                process::exit(1);
+            });
+    }
+
+    if let Some(address) = exit_cell {
+        if state == MimaState::Halted {
+            process::exit((m.get_memory(address) & 0xFF) as i32);
+        }
     }
 }
@@ -1,15 +1,353 @@
 //! Representation of the MIMA firmware in memory.
 
-use std::collections::HashMap;
-use std::io::{self, Write, BufRead};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{self, Write, BufRead, Read};
+use std::error::Error;
+use std::fmt::{self, Formatter, Display};
+use std::str::FromStr;
 
-use super::util;
+use super::{masks, util};
 
 /// Type of a microinstruction.
 pub type Microinstruction = u32;
 
+/// Magic bytes at the start of a firmware in the binary format.
+pub const BINARY_MAGIC: &'static [u8; 4] = b"MIFW";
+
+/// Operation that the ALU can execute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AluOp {
+    /// Do nothing (`000`).
+    Noop,
+    /// Add X and Y (`001`).
+    Add,
+    /// Rotate X to the right (`010`).
+    Rar,
+    /// Binary-and X and Y (`011`).
+    And,
+    /// Binary-or X and Y (`100`).
+    Or,
+    /// Binary-xor X and Y (`101`).
+    Xor,
+    /// Build the ones-complement of X (`110`).
+    Not,
+    /// Compare X and Y (`111`).
+    Eql,
+}
+
+impl AluOp {
+    /// Return the operation for the given ALU control bits (`C2 C1 C0`).
+    ///
+    /// Returns `None` if the value doesn't fit into three bits.
+    ///
+    /// ```rust
+    /// use mimar::firmware::AluOp;
+    /// assert_eq!(AluOp::from_bits(0b001), Some(AluOp::Add));
+    /// assert_eq!(AluOp::from_bits(0b111), Some(AluOp::Eql));
+    /// assert_eq!(AluOp::from_bits(8), None);
+    /// ```
+    pub fn from_bits(bits: u32) -> Option<AluOp> {
+        match bits {
+            0 => Some(AluOp::Noop),
+            1 => Some(AluOp::Add),
+            2 => Some(AluOp::Rar),
+            3 => Some(AluOp::And),
+            4 => Some(AluOp::Or),
+            5 => Some(AluOp::Xor),
+            6 => Some(AluOp::Not),
+            7 => Some(AluOp::Eql),
+            _ => None,
+        }
+    }
+
+    /// Return the ALU control bits (`C2 C1 C0`) of this operation.
+    ///
+    /// The bits are not shifted to their position in the microinstruction,
+    /// use `masks::ALU_SHIFT` for that.
+    pub fn to_bits(&self) -> u32 {
+        match *self {
+            AluOp::Noop => 0,
+            AluOp::Add => 1,
+            AluOp::Rar => 2,
+            AluOp::And => 3,
+            AluOp::Or => 4,
+            AluOp::Xor => 5,
+            AluOp::Not => 6,
+            AluOp::Eql => 7,
+        }
+    }
+}
+
+impl Default for AluOp {
+    fn default() -> AluOp {
+        AluOp::Noop
+    }
+}
+
+impl Display for AluOp {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let name = match *self {
+            AluOp::Noop => "noop",
+            AluOp::Add => "add",
+            AluOp::Rar => "rar",
+            AluOp::And => "and",
+            AluOp::Or => "or",
+            AluOp::Xor => "xor",
+            AluOp::Not => "not",
+            AluOp::Eql => "eql",
+        };
+        write!(fmt, "{}", name)
+    }
+}
+
+/// Error for unknown ALU operations, used for `std::str::FromStr`.
+pub struct UnknownAluOp;
+
+/// Parse an ALU operation, case-insensitive.
+///
+/// Besides the names used by `Display`, the control bits (like `001`) and the
+/// aliases `rotate`, `complement`, `equal`, `cmp` and `compare` are accepted.
+///
+/// ```rust
+/// use mimar::firmware::AluOp;
+/// assert_eq!("ADD".parse::<AluOp>().ok(), Some(AluOp::Add));
+/// assert_eq!("010".parse::<AluOp>().ok(), Some(AluOp::Rar));
+/// assert_eq!("compare".parse::<AluOp>().ok(), Some(AluOp::Eql));
+/// assert!("sub".parse::<AluOp>().is_err());
+/// ```
+impl FromStr for AluOp {
+    type Err = UnknownAluOp;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase() as &str {
+            "noop" | "000" => Ok(AluOp::Noop),
+            "add" | "001" => Ok(AluOp::Add),
+            "rar" | "rotate" | "010" => Ok(AluOp::Rar),
+            "and" | "011" => Ok(AluOp::And),
+            "or" | "100" => Ok(AluOp::Or),
+            "xor" | "101" => Ok(AluOp::Xor),
+            "not" | "complement" | "110" => Ok(AluOp::Not),
+            "eql" | "equal" | "cmp" | "compare" | "111" => Ok(AluOp::Eql),
+            _ => Err(UnknownAluOp),
+        }
+    }
+}
+
+/// A microinstruction with its control bits split into single fields.
+///
+/// The `_read` fields denote that the register reads from the bus, the
+/// `_write` fields denote that the register writes its value to the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct DecodedMicroinstruction {
+    pub accu_read: bool,
+    pub accu_write: bool,
+    pub x_read: bool,
+    pub y_read: bool,
+    pub z_write: bool,
+    pub one_write: bool,
+    pub iar_read: bool,
+    pub iar_write: bool,
+    pub ir_read: bool,
+    pub ir_write: bool,
+    pub sdr_read: bool,
+    pub sdr_write: bool,
+    pub sar_read: bool,
+    /// The ALU operation.
+    pub alu: AluOp,
+    pub mem_read: bool,
+    pub mem_write: bool,
+    /// The address of the next microinstruction.
+    pub next: u8,
+}
+
+impl DecodedMicroinstruction {
+    /// Split the given microinstruction into its fields.
+    ///
+    /// ```rust
+    /// use mimar::firmware::DecodedMicroinstruction;
+    /// use mimar::masks;
+    /// let word = masks::IR_WRITE | masks::SAR_READ | masks::MEM_READ | 0x06;
+    /// let decoded = DecodedMicroinstruction::decode(word);
+    /// assert!(decoded.ir_write && decoded.sar_read && decoded.mem_read);
+    /// assert!(!decoded.ir_read);
+    /// assert_eq!(decoded.next, 0x06);
+    /// assert_eq!(decoded.encode(), word);
+    /// ```
+    pub fn decode(word: Microinstruction) -> DecodedMicroinstruction {
+        DecodedMicroinstruction {
+            accu_read: word & masks::ACCU_READ > 0,
+            accu_write: word & masks::ACCU_WRITE > 0,
+            x_read: word & masks::X_READ > 0,
+            y_read: word & masks::Y_READ > 0,
+            z_write: word & masks::Z_WRITE > 0,
+            one_write: word & masks::ONE_WRITE > 0,
+            iar_read: word & masks::IAR_READ > 0,
+            iar_write: word & masks::IAR_WRITE > 0,
+            ir_read: word & masks::IR_READ > 0,
+            ir_write: word & masks::IR_WRITE > 0,
+            sdr_read: word & masks::SDR_READ > 0,
+            sdr_write: word & masks::SDR_WRITE > 0,
+            sar_read: word & masks::SAR_READ > 0,
+            // The mask leaves only three bits, so this can't fail
+            alu: AluOp::from_bits(masks::alu_bits(word)).unwrap(),
+            mem_read: word & masks::MEM_READ > 0,
+            mem_write: word & masks::MEM_WRITE > 0,
+            next: masks::micro_next(word),
+        }
+    }
+
+    /// Build the microinstruction word from the fields.
+    ///
+    /// This is the inverse of `decode`.
+    pub fn encode(&self) -> Microinstruction {
+        let flags = [
+            (self.accu_read, masks::ACCU_READ),
+            (self.accu_write, masks::ACCU_WRITE),
+            (self.x_read, masks::X_READ),
+            (self.y_read, masks::Y_READ),
+            (self.z_write, masks::Z_WRITE),
+            (self.one_write, masks::ONE_WRITE),
+            (self.iar_read, masks::IAR_READ),
+            (self.iar_write, masks::IAR_WRITE),
+            (self.ir_read, masks::IR_READ),
+            (self.ir_write, masks::IR_WRITE),
+            (self.sdr_read, masks::SDR_READ),
+            (self.sdr_write, masks::SDR_WRITE),
+            (self.sar_read, masks::SAR_READ),
+            (self.mem_read, masks::MEM_READ),
+            (self.mem_write, masks::MEM_WRITE),
+        ];
+        let mut word = flags.iter()
+            .filter(|&&(set, _)| set)
+            .fold(0, |word, &(_, bit)| word | bit);
+        word |= self.alu.to_bits() << masks::ALU_SHIFT;
+        word | self.next as u32
+    }
+}
+
+/// Special behaviour of an instruction that can't be expressed in microcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum InstructionKind {
+    /// The instruction just executes its microcode.
+    Normal,
+    /// The instruction halts the MIMA.
+    Halt,
+    /// The instruction jumps to its argument if the accumulator is negative.
+    ConditionalJumpNegative,
+}
+
+impl InstructionKind {
+    /// Guess the kind of an instruction from its mnemonic.
+    ///
+    /// This is used for firmwares which don't specify the kind, where `HALT`
+    /// and `JMN` were hard-coded.
+    pub fn from_mnemonic(mnemonic: &str) -> InstructionKind {
+        match mnemonic {
+            "HALT" => InstructionKind::Halt,
+            "JMN" => InstructionKind::ConditionalJumpNegative,
+            _ => InstructionKind::Normal,
+        }
+    }
+}
+
+impl Default for InstructionKind {
+    fn default() -> InstructionKind {
+        InstructionKind::Normal
+    }
+}
+
+impl Display for InstructionKind {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let name = match *self {
+            InstructionKind::Normal => "normal",
+            InstructionKind::Halt => "halt",
+            InstructionKind::ConditionalJumpNegative => "conditional-neg",
+        };
+        write!(fmt, "{}", name)
+    }
+}
+
+/// Error for unknown instruction kinds, used for `std::str::FromStr`.
+pub struct UnknownInstructionKind;
+
+impl FromStr for InstructionKind {
+    type Err = UnknownInstructionKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(InstructionKind::Normal),
+            "halt" => Ok(InstructionKind::Halt),
+            "conditional-neg" => Ok(InstructionKind::ConditionalJumpNegative),
+            _ => Err(UnknownInstructionKind),
+        }
+    }
+}
+
+/// The meaning of the argument of an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OperandKind {
+    /// The argument is a memory address, e.g. for `LDV`.
+    Address,
+    /// The argument is a constant, e.g. for `LDC`.
+    Constant,
+    /// The instruction has no argument, e.g. `HALT`.
+    None,
+}
+
+impl OperandKind {
+    /// Guess the operand kind of an instruction from its opcode.
+    ///
+    /// This is used for firmwares which don't specify the operand kind. Opcode
+    /// 0 (`LDC`) takes a constant, the other short opcodes take an address and
+    /// extended opcodes take no argument.
+    ///
+    /// ```rust
+    /// use mimar::firmware::OperandKind;
+    /// assert_eq!(OperandKind::from_opcode(0x0), OperandKind::Constant);
+    /// assert_eq!(OperandKind::from_opcode(0x1), OperandKind::Address);
+    /// assert_eq!(OperandKind::from_opcode(0xF0), OperandKind::None);
+    /// ```
+    pub fn from_opcode(opcode: u8) -> OperandKind {
+        match opcode {
+            0x0 => OperandKind::Constant,
+            0x1..=0xF => OperandKind::Address,
+            _ => OperandKind::None,
+        }
+    }
+}
+
+impl Display for OperandKind {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let name = match *self {
+            OperandKind::Address => "address",
+            OperandKind::Constant => "constant",
+            OperandKind::None => "none",
+        };
+        write!(fmt, "{}", name)
+    }
+}
+
+/// Error for unknown operand kinds, used for `std::str::FromStr`.
+pub struct UnknownOperandKind;
+
+impl FromStr for OperandKind {
+    type Err = UnknownOperandKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "address" => Ok(OperandKind::Address),
+            "constant" => Ok(OperandKind::Constant),
+            "none" => Ok(OperandKind::None),
+            _ => Err(UnknownOperandKind),
+        }
+    }
+}
+
 /// A single instruction.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Instruction {
     /// The numeric opcode, as found in the compiled assembly.
     pub opcode: u8,
@@ -17,15 +355,184 @@ pub struct Instruction {
     pub mnemonic: String,
     /// The start of the instruction in the compiled firmware memory.
     pub start: u8,
+    /// Special behaviour of the instruction.
+    pub kind: InstructionKind,
+    /// The meaning of the argument.
+    pub operand: OperandKind,
+}
+
+/// Structural error in a firmware, found by `Firmware::validate` or
+/// `Firmware::merge`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FirmwareError {
+    /// The instruction with the given opcode starts at an empty cell.
+    EmptyStart(u8),
+    /// The microcode of the instruction with the given opcode never returns to
+    /// the fetch phase.
+    EndlessLoop(u8),
+    /// The given opcode is defined more than once.
+    DuplicateOpcode(u8),
+    /// The microcode would overwrite the used cell at the given address.
+    AddressInUse(u8),
+    /// The microcode doesn't fit into the firmware memory.
+    OutOfMemory,
+    /// The opcode is neither a 4 bit opcode nor an extended opcode `0xFn`.
+    InvalidOpcode(u8),
+}
+
+impl Display for FirmwareError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match *self {
+            FirmwareError::EmptyStart(o) |
+            FirmwareError::EndlessLoop(o) |
+            FirmwareError::DuplicateOpcode(o) |
+            FirmwareError::AddressInUse(o) |
+            FirmwareError::InvalidOpcode(o) =>
+                write!(fmt, "{}: {:#x}", self.description(), o),
+            FirmwareError::OutOfMemory => write!(fmt, "{}", self.description()),
+        }
+    }
+}
+
+impl Error for FirmwareError {
+    fn description(&self) -> &'static str {
+        match *self {
+            FirmwareError::EmptyStart(_) => "instruction starts at an empty cell",
+            FirmwareError::EndlessLoop(_) => "instruction never returns to the fetch phase",
+            FirmwareError::DuplicateOpcode(_) => "opcode defined more than once",
+            FirmwareError::AddressInUse(_) => "microinstruction address already in use",
+            FirmwareError::OutOfMemory => "firmware memory is full",
+            FirmwareError::InvalidOpcode(_) => "opcode can't be encoded",
+        }
+    }
+}
+
+/// Error that may arise when loading a firmware.
+#[derive(Debug)]
+pub enum FirmwareLoadError {
+    /// A field is missing in the line with the given 1-based line number.
+    MissingField(usize),
+    /// A number couldn't be parsed in the line with the given 1-based line
+    /// number.
+    InvalidNumber(usize),
+    /// Underlying IO error.
+    IOError(io::Error),
+}
+
+impl Display for FirmwareLoadError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match *self {
+            FirmwareLoadError::MissingField(line_no) |
+            FirmwareLoadError::InvalidNumber(line_no) =>
+                write!(fmt, "{} (line {})", self.description(), line_no),
+            FirmwareLoadError::IOError(ref err) => write!(fmt, "{}: {}", self.description(), err),
+        }
+    }
+}
+
+impl From<io::Error> for FirmwareLoadError {
+    fn from(err: io::Error) -> FirmwareLoadError {
+        FirmwareLoadError::IOError(err)
+    }
+}
+
+impl Error for FirmwareLoadError {
+    fn description(&self) -> &'static str {
+        match *self {
+            FirmwareLoadError::MissingField(_) => "missing field",
+            FirmwareLoadError::InvalidNumber(_) => "invalid number",
+            FirmwareLoadError::IOError(_) => "underlying IO error",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            FirmwareLoadError::IOError(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// The default firmware, compiled from `default-fw.txt` with `mimar-fwc`.
+///
+/// Regenerate it with `mimar-fwc src/default-fw.txt -o src/default.mimafw`
+/// after changing the source.
+static STOCK_FIRMWARE: &'static str = include_str!("default.mimafw");
+
+/// Return the compiled default firmware.
+///
+/// This is the firmware that `mimar-fwc --default` produces, so it can run
+/// programs assembled with `mimar-asm`.
+///
+/// ```rust
+/// use mimar::Mima;
+/// use mimar::firmware::stock_firmware;
+/// let mut m = Mima::new();
+/// m.firmware = stock_firmware();
+/// assert_eq!(m.firmware.find_instruction_by_mnemonic("ADD").unwrap().opcode, 0x3);
+/// ```
+pub fn stock_firmware() -> Firmware {
+    Firmware::load_from_str(STOCK_FIRMWARE).expect("stock firmware is valid")
+}
+
+/// Take the next field of a firmware line and parse it as number.
+fn parse_field<'a, I>(fields: &mut I, line_no: usize) -> Result<i32, FirmwareLoadError>
+    where I: Iterator<Item=&'a str>
+{
+    let field = try!(fields.next().ok_or(FirmwareLoadError::MissingField(line_no)));
+    util::parse_num(field).ok_or(FirmwareLoadError::InvalidNumber(line_no))
 }
 
 /// The firmware which the MIMA runs.
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "SerializedFirmware"))]
 pub struct Firmware {
-    /// All implemented instructions.
-    pub instructions: Vec<Instruction>,
+    /// All implemented instructions, see `instructions`.
+    instructions: Vec<Instruction>,
     /// The code for each instruction.
     pub code: HashMap<u8, Microinstruction>,
+    /// Position of each opcode in `instructions`, indexed by the opcode.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    opcode_index: Vec<Option<usize>>,
+    /// Maps mnemonics (in ASCII lowercase) to their position in
+    /// `instructions`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    mnemonic_index: HashMap<String, usize>,
+}
+
+// The lookup tables are derived from `instructions`, so they are left out of
+// the comparison. This also makes firmwares deserialized with serde compare
+// equal, as their tables are skipped. The order of the instructions doesn't
+// matter either, as it isn't preserved by `save`.
+impl PartialEq for Firmware {
+    fn eq(&self, other: &Firmware) -> bool {
+        self.sorted_instructions() == other.sorted_instructions() && self.code == other.code
+    }
+}
+
+impl Eq for Firmware {}
+
+/// The fields of `Firmware` that are deserialized, the lookup tables are
+/// rebuilt afterwards.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct SerializedFirmware {
+    instructions: Vec<Instruction>,
+    code: HashMap<u8, Microinstruction>,
+}
+
+#[cfg(feature = "serde")]
+impl From<SerializedFirmware> for Firmware {
+    fn from(serialized: SerializedFirmware) -> Firmware {
+        let mut firmware = Firmware {
+            instructions: serialized.instructions,
+            code: serialized.code,
+            ..Default::default()
+        };
+        firmware.rebuild_index();
+        firmware
+    }
 }
 
 impl Firmware {
@@ -34,32 +541,315 @@ impl Firmware {
         Default::default()
     }
 
+    /// Return all implemented instructions, in the order they were inserted.
+    ///
+    /// Use `insert_instruction` to add or replace instructions.
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
     /// Insert the given instruction.
     ///
     /// This overrides any older instruction with the same opcode.
     pub fn insert_instruction(&mut self, instr: Instruction) {
         self.instructions.retain(|i| i.opcode != instr.opcode);
         self.instructions.push(instr);
+        self.rebuild_index();
+    }
+
+    /// Rebuild the lookup tables for `find_instruction` and
+    /// `find_instruction_by_mnemonic`.
+    fn rebuild_index(&mut self) {
+        self.opcode_index = vec![None; 256];
+        self.mnemonic_index.clear();
+        // Go backwards, so that the first matching instruction wins
+        for (i, instr) in self.instructions.iter().enumerate().rev() {
+            self.opcode_index[instr.opcode as usize] = Some(i);
+            self.mnemonic_index.insert(instr.mnemonic.to_ascii_lowercase(), i);
+        }
     }
 
     /// Find the instruction with the given opcode.
     pub fn find_instruction(&self, opcode: u8) -> Option<&Instruction> {
+        self.opcode_index.get(opcode as usize)
+            .and_then(|&i| i)
+            .and_then(|i| self.instructions.get(i))
+    }
+
+    /// Find the instruction with the given mnemonic.
+    ///
+    /// The comparison ignores the ASCII case, so `add` finds the instruction
+    /// `ADD`. If multiple instructions match, the first one is returned.
+    ///
+    /// ```rust
+    /// use mimar::firmware::{Firmware, Instruction, InstructionKind, OperandKind};
+    /// let mut firmware = Firmware::new();
+    /// firmware.insert_instruction(Instruction {
+    ///     opcode: 0x3,
+    ///     mnemonic: "ADD".into(),
+    ///     start: 0x05,
+    ///     kind: InstructionKind::Normal,
+    ///     operand: OperandKind::Address,
+    /// });
+    /// firmware.insert_instruction(Instruction {
+    ///     opcode: 0x4,
+    ///     mnemonic: "AND".into(),
+    ///     start: 0x0B,
+    ///     kind: InstructionKind::Normal,
+    ///     operand: OperandKind::Address,
+    /// });
+    /// assert_eq!(firmware.find_instruction_by_mnemonic("ADD").unwrap().opcode, 0x3);
+    /// assert_eq!(firmware.find_instruction_by_mnemonic("add").unwrap().opcode, 0x3);
+    /// assert_eq!(firmware.find_instruction_by_mnemonic("And").unwrap().opcode, 0x4);
+    /// assert!(firmware.find_instruction_by_mnemonic("OR").is_none());
+    /// ```
+    pub fn find_instruction_by_mnemonic(&self, mnemonic: &str) -> Option<&Instruction> {
+        self.mnemonic_index.get(&mnemonic.to_ascii_lowercase())
+            .and_then(|&i| self.instructions.get(i))
+    }
+
+    /// Find all instructions with the given mnemonic (ignoring the case).
+    ///
+    /// `find_instruction_by_mnemonic` returns the first of them.
+    ///
+    /// ```rust
+    /// use mimar::firmware::{Firmware, Instruction, InstructionKind, OperandKind};
+    /// let mut firmware = Firmware::new();
+    /// for &opcode in &[0x3, 0x5] {
+    ///     firmware.insert_instruction(Instruction {
+    ///         opcode: opcode,
+    ///         mnemonic: "ADD".into(),
+    ///         start: 0x05,
+    ///         kind: InstructionKind::Normal,
+    ///         operand: OperandKind::Address,
+    ///     });
+    /// }
+    /// let opcodes = firmware.instructions_with_mnemonic("add").iter()
+    ///     .map(|i| i.opcode)
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(opcodes, vec![0x3, 0x5]);
+    /// assert!(firmware.instructions_with_mnemonic("SUB").is_empty());
+    /// ```
+    pub fn instructions_with_mnemonic(&self, mnemonic: &str) -> Vec<&Instruction> {
+        self.instructions.iter()
+            .filter(|i| i.mnemonic.eq_ignore_ascii_case(mnemonic))
+            .collect()
+    }
+
+    /// Check the firmware for structural errors.
+    ///
+    /// This checks that
+    ///
+    /// * every instruction starts at a populated cell,
+    /// * the microcode of every instruction returns to the fetch phase,
+    /// * no opcode is defined twice and
+    /// * every opcode can be encoded, i.e. it is either a normal opcode
+    ///   (`0x0` to `0xE`) or an extended opcode (`0xF0` to `0xFF`).
+    ///
+    /// Instructions with a special kind (like `HALT` and `JMN`) don't need any
+    /// microcode and are exempt from the first two checks.
+    ///
+    /// ```rust
+    /// use mimar::firmware::{Firmware, FirmwareError, Instruction, InstructionKind, OperandKind};
+    /// use mimar::masks;
+    /// let mut firmware = Firmware::new();
+    /// // a microinstruction that jumps to itself
+    /// firmware.set_memory(0x10, masks::MEM_READ | 0x10);
+    /// firmware.insert_instruction(Instruction {
+    ///     opcode: 0x1,
+    ///     mnemonic: "LOOP".into(),
+    ///     start: 0x10,
+    ///     kind: InstructionKind::Normal,
+    ///     operand: OperandKind::Address,
+    /// });
+    /// assert_eq!(firmware.validate(), Err(vec![FirmwareError::EndlessLoop(0x1)]));
+    /// ```
+    pub fn validate(&self) -> Result<(), Vec<FirmwareError>> {
+        let mut errors = Vec::new();
+        let mut seen = HashSet::new();
         for instr in &self.instructions {
-            if instr.opcode == opcode {
-                return Some(instr);
+            if !seen.insert(instr.opcode) {
+                errors.push(FirmwareError::DuplicateOpcode(instr.opcode));
+            }
+            // 0xF marks an extended opcode, so it can't be used on its own
+            if instr.opcode >= 0xF && instr.opcode < 0xF0 {
+                errors.push(FirmwareError::InvalidOpcode(instr.opcode));
+            }
+            if instr.kind != InstructionKind::Normal {
+                continue;
+            }
+            if self.get_memory(instr.start) == 0 {
+                errors.push(FirmwareError::EmptyStart(instr.opcode));
+                continue;
+            }
+            // Follow the chain, it has to end at the fetch phase before it
+            // visited every cell.
+            let mut address = instr.start;
+            let mut steps = 0;
+            while address != 0 && address != 0xFF {
+                if steps > 0xFF {
+                    errors.push(FirmwareError::EndlessLoop(instr.opcode));
+                    break;
+                }
+                address = masks::micro_next(self.get_memory(address));
+                steps += 1;
             }
         }
-        None
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 
-    /// Find the instruction with the given mnemonic
-    pub fn find_instruction_by_mnemonic(&self, mnemonic: &str) -> Option<&Instruction> {
-        for instr in &self.instructions {
-            if instr.mnemonic == mnemonic {
-                return Some(instr);
+    /// Count the cycles an instruction takes, including the fetch phase.
+    ///
+    /// The count consists of the microinstructions of the fetch phase, one
+    /// cycle for the decode phase and the microinstructions of the
+    /// instruction. Memory accesses don't add any cycles, as their three cycles
+    /// are spelled out in the microcode. Instructions with a special kind only
+    /// need the fetch and decode phase.
+    ///
+    /// `None` is returned if there is no instruction with the given opcode or
+    /// if its microcode never returns to the fetch phase.
+    ///
+    /// ```rust
+    /// use mimar::firmware::{Firmware, Instruction, InstructionKind, OperandKind};
+    /// let mut firmware = Firmware::new();
+    /// firmware.load_memory(&[0x01, 0x02, 0x03, 0x04, 0xFF, 0x06, 0x07, 0x0]);
+    /// firmware.insert_instruction(Instruction {
+    ///     opcode: 0x1,
+    ///     mnemonic: "LDV".into(),
+    ///     start: 0x05,
+    ///     kind: InstructionKind::Normal,
+    ///     operand: OperandKind::Address,
+    /// });
+    /// assert_eq!(firmware.instruction_cycle_count(0x1), Some(9));
+    /// assert_eq!(firmware.instruction_cycle_count(0x2), None);
+    /// ```
+    pub fn instruction_cycle_count(&self, opcode: u8) -> Option<u32> {
+        let instruction = match self.find_instruction(opcode) {
+            Some(i) => i,
+            None => return None,
+        };
+        // Count the cells from start until the chain reaches the given end
+        let chain_length = |start: u8, end: u8| {
+            let mut address = start;
+            let mut count = 0;
+            loop {
+                count += 1;
+                if count > 0xFF {
+                    return None;
+                }
+                address = masks::micro_next(self.get_memory(address));
+                if address == end {
+                    return Some(count);
+                }
             }
+        };
+        let fetch = match chain_length(0x00, 0xFF) {
+            Some(count) => count,
+            None => return None,
+        };
+        if instruction.kind != InstructionKind::Normal {
+            return Some(fetch + 1);
         }
-        None
+        chain_length(instruction.start, 0x00).map(|count| fetch + 1 + count)
+    }
+
+    /// Import the instructions of another firmware.
+    ///
+    /// The microcode of the imported instructions is placed at `base_address`,
+    /// or after the highest used cell if no address is given. The start
+    /// addresses and the next fields of the microcode are relocated
+    /// accordingly, while jumps to the fetch (0x00) and decode (0xFF) phase
+    /// are kept. The fetch phase of `other` is not imported.
+    ///
+    /// Nothing is changed if an imported opcode is already defined or the
+    /// microcode doesn't fit.
+    ///
+    /// ```rust
+    /// use mimar::firmware::{Firmware, FirmwareError, Instruction, InstructionKind, OperandKind};
+    /// use mimar::masks;
+    /// let mut firmware = Firmware::new();
+    /// firmware.load_memory(&[0x01, 0x02, 0xFF, masks::MEM_READ]);
+    /// firmware.insert_instruction(Instruction {
+    ///     opcode: 0x1,
+    ///     mnemonic: "LDV".into(),
+    ///     start: 0x03,
+    ///     kind: InstructionKind::Normal,
+    ///     operand: OperandKind::Address,
+    /// });
+    ///
+    /// let mut other = Firmware::new();
+    /// other.load_memory(&[0x01, 0xFF, masks::ACCU_READ | 0x03, masks::ACCU_WRITE]);
+    /// other.insert_instruction(Instruction {
+    ///     opcode: 0x2,
+    ///     mnemonic: "NEW".into(),
+    ///     start: 0x02,
+    ///     kind: InstructionKind::Normal,
+    ///     operand: OperandKind::Address,
+    /// });
+    ///
+    /// firmware.merge(&other, None).unwrap();
+    /// assert_eq!(firmware.find_instruction(0x2).unwrap().start, 0x04);
+    /// assert_eq!(firmware.get_memory(0x04), masks::ACCU_READ | 0x05);
+    /// assert_eq!(firmware.get_memory(0x05), masks::ACCU_WRITE);
+    /// assert_eq!(firmware.merge(&other, None), Err(FirmwareError::DuplicateOpcode(0x2)));
+    /// ```
+    pub fn merge(&mut self, other: &Firmware, base_address: Option<u8>)
+                 -> Result<(), FirmwareError> {
+        for instr in &other.instructions {
+            if self.find_instruction(instr.opcode).is_some() {
+                return Err(FirmwareError::DuplicateOpcode(instr.opcode));
+            }
+        }
+
+        // Collect the cells that are used by the imported instructions
+        let mut used = BTreeSet::new();
+        for instr in other.instructions.iter().filter(|i| i.kind == InstructionKind::Normal) {
+            let mut address = instr.start;
+            while address != 0x00 && address != 0xFF && used.insert(address) {
+                address = masks::micro_next(other.get_memory(address));
+            }
+        }
+
+        let base = match base_address {
+            Some(base) => base as usize,
+            None => {
+                self.code.iter()
+                    .filter(|&(_, &value)| value != 0)
+                    .map(|(&address, _)| address as usize + 1)
+                    .max()
+                    .unwrap_or(0)
+            },
+        };
+        // 0xFF is reserved for the decode phase
+        if base + used.len() > 0xFF {
+            return Err(FirmwareError::OutOfMemory);
+        }
+        let relocation = used.iter()
+            .enumerate()
+            .map(|(i, &address)| (address, (base + i) as u8))
+            .collect::<HashMap<_, _>>();
+        for &address in relocation.values() {
+            if self.get_memory(address) != 0 {
+                return Err(FirmwareError::AddressInUse(address));
+            }
+        }
+
+        for (&old, &new) in &relocation {
+            let word = other.get_memory(old);
+            let next = masks::micro_next(word);
+            let next = relocation.get(&next).cloned().unwrap_or(next);
+            self.set_memory(new, masks::set_next(word, next));
+        }
+        for instr in &other.instructions {
+            let mut instr = instr.clone();
+            instr.start = relocation.get(&instr.start).cloned().unwrap_or(instr.start);
+            self.insert_instruction(instr);
+        }
+        Ok(())
     }
 
     /// Load memory from a slice.
@@ -85,40 +875,286 @@ impl Firmware {
     }
 
     /// Output the firmware to the given writer.
+    ///
+    /// The instructions are written sorted by opcode and the microcode sorted
+    /// by address, so the output only depends on the content of the firmware.
     pub fn save<W: Write>(&self, out: &mut W) -> io::Result<()> {
-        for inst in &self.instructions {
-            try!(writeln!(out, "I:{} {:#04x} {:#04x}", inst.mnemonic, inst.opcode, inst.start));
+        self.save_text(out, false)
+    }
+
+    /// Output the firmware to the given writer, leaving out empty cells.
+    ///
+    /// Missing cells are loaded as 0, so the output can be read by `load` just
+    /// like the output of `save`.
+    ///
+    /// ```rust
+    /// use mimar::firmware::{Firmware, Instruction, InstructionKind, OperandKind};
+    /// let mut firmware = Firmware::new();
+    /// firmware.insert_instruction(Instruction {
+    ///     opcode: 0x1,
+    ///     mnemonic: "LDV".into(),
+    ///     start: 0x05,
+    ///     kind: InstructionKind::Normal,
+    ///     operand: OperandKind::Address,
+    /// });
+    /// firmware.load_memory(&[0x1, 0x2, 0x3, 0x4, 0xFF, 0x6]);
+    /// let mut output = Vec::new();
+    /// firmware.save_compact(&mut output).unwrap();
+    /// assert_eq!(output.iter().filter(|&&b| b == b'M').count(), 6);
+    /// let loaded = Firmware::load(&output as &[u8]).unwrap();
+    /// assert_eq!(loaded, firmware);
+    /// ```
+    pub fn save_compact<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.save_text(out, true)
+    }
+
+    /// Return the instructions sorted by opcode, so that the output doesn't
+    /// depend on the order in which they were defined.
+    fn sorted_instructions(&self) -> Vec<&Instruction> {
+        let mut instructions = self.instructions.iter().collect::<Vec<_>>();
+        instructions.sort_by_key(|i| i.opcode);
+        instructions
+    }
+
+    fn save_text<W: Write>(&self, out: &mut W, compact: bool) -> io::Result<()> {
+        for inst in self.sorted_instructions() {
+            try!(write!(out, "I:{} {:#04x} {:#04x}", inst.mnemonic, inst.opcode, inst.start));
+            if inst.kind != InstructionKind::from_mnemonic(&inst.mnemonic) {
+                try!(write!(out, " {}", inst.kind));
+            }
+            if inst.operand != OperandKind::from_opcode(inst.opcode) {
+                try!(write!(out, " {}", inst.operand));
+            }
+            try!(writeln!(out, ""));
         }
         try!(writeln!(out, ""));
         for i in 0..256 {
             let i = i as u8;
-            try!(writeln!(out, "M:{:#04x} {:#09x}", i, self.get_memory(i)));
+            let value = self.get_memory(i);
+            if !compact || value != 0 {
+                try!(writeln!(out, "M:{:#04x} {:#09x}", i, value));
+            }
         }
         Ok(())
     }
 
+    /// Output the microcode as Logisim ROM image to the given writer.
+    ///
+    /// The image uses the `v2.0 raw` format and contains all 256 words, so it
+    /// can be loaded into a ROM with 8 address and 32 data bits. The
+    /// instructions are not part of the image.
+    ///
+    /// ```rust
+    /// use mimar::firmware::Firmware;
+    /// let mut firmware = Firmware::new();
+    /// firmware.load_memory(&[0x1, 0xABC]);
+    /// let mut output = Vec::new();
+    /// firmware.save_logisim(&mut output).unwrap();
+    /// let output = String::from_utf8(output).unwrap();
+    /// assert!(output.starts_with("v2.0 raw\n1 abc 0 0 0 0 0 0\n"));
+    /// assert_eq!(output.lines().count(), 33);
+    /// ```
+    pub fn save_logisim<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        try!(writeln!(out, "v2.0 raw"));
+        for row in 0..32 {
+            let words = (0..8)
+                .map(|i| format!("{:x}", self.get_memory((row * 8 + i) as u8)))
+                .collect::<Vec<_>>();
+            try!(writeln!(out, "{}", words.join(" ")));
+        }
+        Ok(())
+    }
+
+    /// Output the firmware in the binary format to the given writer.
+    ///
+    /// The format consists of the magic bytes `MIFW`, the number of
+    /// instructions as little-endian `u16` and the instructions. Each
+    /// instruction is saved as opcode (one byte), the length of the mnemonic
+    /// (one byte), the mnemonic, the start (one byte) and the kind (one byte).
+    /// The lower nibble of the kind is `0` for normal, `1` for halt and `2` for
+    /// a conditional jump, the upper nibble is the operand kind: `1` for an
+    /// address, `2` for a constant and `3` for none. If the operand kind is `0`,
+    /// it is guessed from the opcode. After the instructions follow the 256
+    /// microinstructions as little-endian `u32`.
+    ///
+    /// ```rust
+    /// use mimar::firmware::{Firmware, Instruction, InstructionKind, OperandKind};
+    /// let mut firmware = Firmware::new();
+    /// firmware.insert_instruction(Instruction {
+    ///     opcode: 0xF1,
+    ///     mnemonic: "NOT".into(),
+    ///     start: 0x05,
+    ///     kind: InstructionKind::Normal,
+    ///     operand: OperandKind::None,
+    /// });
+    /// firmware.set_memory(0x05, 0x1234567);
+    /// let mut buffer = Vec::new();
+    /// firmware.save_binary(&mut buffer).unwrap();
+    /// let loaded = Firmware::load_binary(&buffer as &[u8]).unwrap();
+    /// assert_eq!(loaded, firmware);
+    /// ```
+    pub fn save_binary<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        try!(out.write_all(BINARY_MAGIC));
+        let count = self.instructions.len() as u16;
+        try!(out.write_all(&[count as u8, (count >> 8) as u8]));
+        for inst in self.sorted_instructions() {
+            let mnemonic = inst.mnemonic.as_bytes();
+            if mnemonic.len() > 0xFF {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "mnemonic too long"));
+            }
+            try!(out.write_all(&[inst.opcode, mnemonic.len() as u8]));
+            try!(out.write_all(mnemonic));
+            let kind = match inst.kind {
+                InstructionKind::Normal => 0,
+                InstructionKind::Halt => 1,
+                InstructionKind::ConditionalJumpNegative => 2,
+            };
+            let operand = match inst.operand {
+                OperandKind::Address => 1,
+                OperandKind::Constant => 2,
+                OperandKind::None => 3,
+            };
+            try!(out.write_all(&[inst.start, operand << 4 | kind]));
+        }
+        for i in 0..256 {
+            let word = self.get_memory(i as u8);
+            try!(out.write_all(&[word as u8, (word >> 8) as u8, (word >> 16) as u8, (word >> 24) as u8]));
+        }
+        Ok(())
+    }
+
+    /// Load the firmware in the binary format from the given reader.
+    ///
+    /// See `save_binary` for a description of the format.
+    pub fn load_binary<R: Read>(mut reader: R) -> io::Result<Firmware> {
+        let mut firmware = Firmware::new();
+        let mut magic = [0; 4];
+        try!(reader.read_exact(&mut magic));
+        if &magic != BINARY_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a binary firmware"));
+        }
+        let mut count = [0; 2];
+        try!(reader.read_exact(&mut count));
+        let count = count[0] as usize | (count[1] as usize) << 8;
+        for _ in 0..count {
+            let mut head = [0; 2];
+            try!(reader.read_exact(&mut head));
+            let mut mnemonic = vec![0; head[1] as usize];
+            try!(reader.read_exact(&mut mnemonic));
+            let mnemonic = try!(String::from_utf8(mnemonic).map_err(
+                |e| io::Error::new(io::ErrorKind::InvalidData, e)));
+            let mut tail = [0; 2];
+            try!(reader.read_exact(&mut tail));
+            let kind = match tail[1] & 0xF {
+                0 => InstructionKind::Normal,
+                1 => InstructionKind::Halt,
+                2 => InstructionKind::ConditionalJumpNegative,
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid instruction kind")),
+            };
+            let operand = match tail[1] >> 4 {
+                0 => OperandKind::from_opcode(head[0]),
+                1 => OperandKind::Address,
+                2 => OperandKind::Constant,
+                3 => OperandKind::None,
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid operand kind")),
+            };
+            firmware.insert_instruction(Instruction {
+                opcode: head[0],
+                mnemonic: mnemonic,
+                start: tail[0],
+                kind: kind,
+                operand: operand,
+            });
+        }
+        for i in 0..256 {
+            let mut word = [0; 4];
+            try!(reader.read_exact(&mut word));
+            let word = word.iter().rev().fold(0, |acc, &b| acc << 8 | b as u32);
+            firmware.set_memory(i as u8, word);
+        }
+        Ok(firmware)
+    }
+
     /// Load the firmware from the given reader.
-    pub fn load<B: BufRead>(reader: B) -> io::Result<Firmware> {
+    ///
+    /// Both the text and the binary format are accepted. Malformed lines are
+    /// reported with their line number.
+    ///
+    /// ```rust
+    /// use mimar::firmware::{Firmware, FirmwareLoadError};
+    /// match Firmware::load_from_str("I:LDV 0x01 0x05\nI:STV 0x02\n") {
+    ///     Err(FirmwareLoadError::MissingField(2)) => (),
+    ///     other => panic!("unexpected result {:?}", other),
+    /// }
+    /// match Firmware::load_from_str("M:0x05 zero\n") {
+    ///     Err(FirmwareLoadError::InvalidNumber(1)) => (),
+    ///     other => panic!("unexpected result {:?}", other),
+    /// }
+    /// ```
+    pub fn load<B: BufRead>(mut reader: B) -> Result<Firmware, FirmwareLoadError> {
+        // A single read may return less than the magic bytes, e.g. on a pipe
+        let mut head = Vec::with_capacity(BINARY_MAGIC.len());
+        while head.len() < BINARY_MAGIC.len() {
+            let mut byte = [0];
+            match reader.read(&mut byte) {
+                Ok(0) => break,
+                Ok(_) => head.push(byte[0]),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let is_binary = &head[..] == BINARY_MAGIC;
+        let reader = io::Cursor::new(head).chain(reader);
+        if is_binary {
+            return Ok(try!(Firmware::load_binary(reader)));
+        }
         let mut firmware = Firmware::new();
-        for line in reader.lines() {
+        for (line_no, line) in reader.lines().enumerate() {
             let line = try!(line);
+            let line_no = line_no + 1;
             if line.starts_with("I:") {
                 let mut split = line[2..].split(" ");
-                let mnemo = split.next().unwrap();
-                let opcode = util::parse_num(split.next().unwrap()).unwrap();
-                let start = util::parse_num(split.next().unwrap()).unwrap();
+                let mnemo = try!(split.next()
+                                 .filter(|m| !m.is_empty())
+                                 .ok_or(FirmwareLoadError::MissingField(line_no)));
+                let opcode = try!(parse_field(&mut split, line_no));
+                let start = try!(parse_field(&mut split, line_no));
+                let extra = split.collect::<Vec<_>>();
+                // The kind is only saved if it can't be guessed from the mnemonic
+                let kind = extra.iter()
+                    .filter_map(|e| e.parse().ok())
+                    .next()
+                    .unwrap_or_else(|| InstructionKind::from_mnemonic(mnemo));
+                let operand = extra.iter()
+                    .filter_map(|e| e.parse().ok())
+                    .next()
+                    .unwrap_or_else(|| OperandKind::from_opcode(opcode as u8));
                 firmware.insert_instruction(Instruction {
                     opcode: opcode as u8,
                     mnemonic: mnemo.into(),
                     start: start as u8,
+                    kind: kind,
+                    operand: operand,
                 });
             } else if line.starts_with("M:") {
                 let mut split = line[2..].split(" ");
-                let adr = util::parse_num(split.next().unwrap()).unwrap();
-                let val = util::parse_num(split.next().unwrap()).unwrap();
+                let adr = try!(parse_field(&mut split, line_no));
+                let val = try!(parse_field(&mut split, line_no));
                 firmware.set_memory(adr as u8, val as u32);
             }
         }
         Ok(firmware)
     }
+
+    /// Load the firmware from the given string.
+    ///
+    /// ```rust
+    /// use mimar::firmware::Firmware;
+    /// let firmware = Firmware::load_from_str("I:LDV 0x01 0x05\nM:0x05 0x1\n").unwrap();
+    /// assert_eq!(firmware.find_instruction(0x1).unwrap().mnemonic, "LDV");
+    /// assert_eq!(firmware.get_memory(0x05), 0x1);
+    /// ```
+    pub fn load_from_str(text: &str) -> Result<Firmware, FirmwareLoadError> {
+        Firmware::load(text.as_bytes())
+    }
 }
@@ -0,0 +1,509 @@
+//! Compiler for firmwares in register transfer notation.
+//!
+//! See [`mimar-fwc`](../../mimar_fwc/index.html) for a description of the
+//! syntax.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display, Write};
+use std::io::{self, BufRead};
+
+use regex::Regex;
+
+use super::{masks, util};
+use super::firmware::{Firmware, Microinstruction, Instruction, InstructionKind, OperandKind, AluOp,
+                      UnknownAluOp, DecodedMicroinstruction};
+use super::registers::{Register, UnknownRegister};
+
+/// Error that may arise when compiling a firmware.
+///
+/// Errors in the source contain the 1-based line number.
+#[derive(Debug)]
+pub enum FwcError {
+    /// A label is defined more than once.
+    DuplicateLabel(usize, String),
+    /// With an explicit fetch phase, the given instruction is defined before
+    /// the fetch phase.
+    MissingFetchPhase(usize, String),
+    /// The opcode is not a number.
+    InvalidOpcode(usize, String),
+    /// The opcode is defined more than once.
+    DuplicateOpcode(usize, u8),
+    /// The word is neither an instruction kind nor an operand kind.
+    UnknownKind(usize, String),
+    /// The raw microinstruction is not a number or wider than 28 bits.
+    InvalidRawWord(usize, String),
+    /// The register transfer in the given line is invalid, e.g. a register
+    /// reads from an empty bus (`RTError::BusEmpty`).
+    ParseError(usize, RTError, String),
+    /// A `goto` uses a label that is never defined.
+    UndefinedLabel(usize, String),
+    /// Underlying IO error.
+    IoError(io::Error),
+}
+
+impl FwcError {
+    /// Return the line of the source that caused the error.
+    ///
+    /// ```rust
+    /// use mimar::fwc::compile;
+    /// let error = compile("define LDC 0x0\n\n-> X\n".as_bytes()).unwrap_err();
+    /// assert_eq!(error.line(), Some(3));
+    /// ```
+    pub fn line(&self) -> Option<usize> {
+        match *self {
+            FwcError::DuplicateLabel(n, _) |
+            FwcError::MissingFetchPhase(n, _) |
+            FwcError::InvalidOpcode(n, _) |
+            FwcError::DuplicateOpcode(n, _) |
+            FwcError::UnknownKind(n, _) |
+            FwcError::InvalidRawWord(n, _) |
+            FwcError::ParseError(n, _, _) |
+            FwcError::UndefinedLabel(n, _) => Some(n),
+            FwcError::IoError(_) => None,
+        }
+    }
+}
+
+impl Display for FwcError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FwcError::DuplicateLabel(n, ref s) |
+            FwcError::MissingFetchPhase(n, ref s) |
+            FwcError::InvalidOpcode(n, ref s) |
+            FwcError::UnknownKind(n, ref s) |
+            FwcError::InvalidRawWord(n, ref s) |
+            FwcError::UndefinedLabel(n, ref s) =>
+                write!(fmt, "line {}: {} {}", n, self.description(), s),
+            FwcError::DuplicateOpcode(n, opcode) =>
+                write!(fmt, "line {}: {} {:#x}", n, self.description(), opcode),
+            FwcError::ParseError(n, e, ref line) =>
+                write!(fmt, "line {}: {}: {}", n, e, line),
+            FwcError::IoError(ref e) => write!(fmt, "{}: {}", self.description(), e),
+        }
+    }
+}
+
+impl Error for FwcError {
+    fn description(&self) -> &str {
+        match *self {
+            FwcError::DuplicateLabel(..) => "duplicated label",
+            FwcError::MissingFetchPhase(..) => "the fetch phase must be defined before",
+            FwcError::InvalidOpcode(..) => "invalid opcode",
+            FwcError::DuplicateOpcode(..) => "duplicated opcode",
+            FwcError::UnknownKind(..) => "unknown instruction or operand kind",
+            FwcError::InvalidRawWord(..) => "invalid raw microinstruction",
+            FwcError::ParseError(..) => "invalid register transfer",
+            FwcError::UndefinedLabel(..) => "undefined label",
+            FwcError::IoError(_) => "IO error",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            FwcError::ParseError(_, ref e, _) => Some(e),
+            FwcError::IoError(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FwcError {
+    fn from(e: io::Error) -> FwcError {
+        FwcError::IoError(e)
+    }
+}
+
+/// Possible errors that might happen when parsing a register-transfer line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RTError {
+    /// Bus already busy because both registers want to write data.
+    BusBusy(Register, Register),
+    /// The register reads from the bus, but no register writes to it.
+    BusEmpty(Register),
+    /// Register name not known.
+    UnknownRegister,
+    /// Register is write only.
+    RegisterReadViolation,
+    /// Register is read only.
+    RegisterWriteViolation,
+    /// Unknown ALU operation
+    InvalidALUInstruction,
+    /// General syntax error
+    SyntaxError,
+}
+
+impl Display for RTError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RTError::BusBusy(first, second) =>
+                write!(fmt, "{}: both {} and {} drive the bus", self.description(), first, second),
+            RTError::BusEmpty(target) =>
+                write!(fmt, "{}: nothing drives the bus for {}", self.description(), target),
+            _ => write!(fmt, "{}", self.description()),
+        }
+    }
+}
+
+impl Error for RTError {
+    fn description(&self) -> &'static str {
+        match *self {
+            RTError::BusBusy(..) => "the bus is already being used",
+            RTError::BusEmpty(_) => "reading from the empty bus",
+            RTError::UnknownRegister => "unknown register",
+            RTError::RegisterReadViolation =>
+                "attempting to read a write-only register",
+            RTError::RegisterWriteViolation =>
+                "attempting to write a read-only register",
+            RTError::InvalidALUInstruction =>
+                "invalid ALU instruction",
+            RTError::SyntaxError => "syntax error",
+        }
+    }
+}
+
+impl From<UnknownRegister> for RTError {
+    fn from(_: UnknownRegister) -> Self {
+        RTError::UnknownRegister
+    }
+}
+
+impl From<UnknownAluOp> for RTError {
+    fn from(_: UnknownAluOp) -> Self {
+        RTError::InvalidALUInstruction
+    }
+}
+
+/// Parse a single line of register-transfer-notation.
+///
+/// The address of the next microinstruction is left at 0.
+///
+/// ```rust
+/// use mimar::fwc::{parse_register_transfer, RTError};
+/// use mimar::masks::*;
+/// use mimar::registers::Register;
+/// assert_eq!(parse_register_transfer("IR -> SAR; R = 1"), Ok(IR_WRITE | SAR_READ | MEM_READ));
+/// assert_eq!(parse_register_transfer("-> Accu"), Err(RTError::BusEmpty(Register::Accu)));
+/// ```
+pub fn parse_register_transfer(line: &str) -> Result<Microinstruction, RTError> {
+    lazy_static! {
+        static ref TRANSFER: Regex = Regex::new("^(\\w+)?\\s*->\\s*(\\w+)$").unwrap();
+        static ref RW_BIT: Regex = Regex::new("^([RrWw])\\s*=\\s*([10])$").unwrap();
+        static ref ALU: Regex = Regex::new("^ALU ([A-Za-z01]+)$").unwrap();
+    }
+    let parts = line.split(';');
+    let mut source: Option<Register> = None;
+    let mut targets: Vec<Register> = Vec::new();
+    let mut alu = 0;
+    let mut r_bit = 0;
+    let mut w_bit = 0;
+    for part in parts {
+        let part = part.trim();
+        // handle register parts like IAR -> IR, a missing source is reported
+        // once the whole line has been parsed
+        if let Some(caps) = TRANSFER.captures(part) {
+            if let Some(src) = caps.at(1) {
+                let src_register = try!(src.parse::<Register>());
+                if let Some(source) = source {
+                    if source != src_register {
+                        return Err(RTError::BusBusy(source, src_register));
+                    }
+                }
+                source = Some(src_register);
+            }
+            let target = try!(caps[2].parse::<Register>());
+            targets.push(target);
+        // handle parts like R=1
+        } else if let Some(caps) = RW_BIT.captures(part) {
+            match &caps[1] {
+                "r" | "R" => r_bit = caps[2].parse().unwrap(),
+                "w" | "W" => w_bit = caps[2].parse().unwrap(),
+                _ => unreachable!(),
+            }
+        // handle parts like ALU add (or ALU 011)
+        } else if let Some(caps) = ALU.captures(part) {
+            let op = try!(caps[1].parse::<AluOp>());
+            alu = op.to_bits() << masks::ALU_SHIFT;
+        } else {
+            return Err(RTError::SyntaxError);
+        }
+    }
+
+    // A register that reads the bus while nothing drives it would fail with
+    // BusEmpty in the simulator, so catch it here
+    if let (None, Some(&target)) = (source, targets.first()) {
+        return Err(RTError::BusEmpty(target));
+    }
+
+    // build the actual instruction word
+    let mut instr: Microinstruction = 0;
+    if let Some(source) = source {
+        if let Some(write_bit) = source.write_bit() {
+            instr |= write_bit;
+        } else {
+            return Err(RTError::RegisterReadViolation);
+        }
+        for target in &targets {
+            if let Some(read_bit) = target.read_bit() {
+                instr |= read_bit;
+            } else {
+                return Err(RTError::RegisterWriteViolation);
+            }
+        }
+    }
+    if r_bit == 1 {
+        instr |= masks::MEM_READ;
+    }
+    if w_bit == 1 {
+        instr |= masks::MEM_WRITE;
+    }
+    Ok(instr | alu)
+}
+
+/// Get the initial fetch phase.
+fn fetch_phase() -> Vec<Microinstruction> {
+    const FETCH_PHASE: &'static str = r#"
+        IAR -> SAR; IAR -> X; R = 1
+        One -> Y; R = 1
+        ALU add; R = 1
+        Z -> IAR
+        SDR -> IR
+    "#;
+    FETCH_PHASE.split("\n")
+        // clean the string
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        // parse it
+        .map(|s| parse_register_transfer(s).unwrap())
+        .enumerate()
+        // add the addresses
+        .map(|(i, v)| v | if i == 4 { 0xFF } else { i as u32 + 1 })
+        .collect()
+}
+
+/// Let the last cell of the current block continue at the given address.
+///
+/// Blocks without any cells (like `HALT`) are left alone.
+fn finish_block(memory: &mut Vec<Microinstruction>, block_start: usize, next: u8) {
+    if memory.len() > block_start {
+        if let Some(n) = memory.last_mut() {
+            *n = masks::set_next(*n, next);
+        }
+    }
+}
+
+/// Compile the firmware source from the given reader.
+///
+/// The built-in fetch phase is added automatically.
+///
+/// ```rust
+/// use mimar::fwc::compile;
+/// let firmware = compile("define LDC 0x0\nIR -> Accu\n".as_bytes()).unwrap();
+/// assert_eq!(firmware.find_instruction(0x0).unwrap().mnemonic, "LDC");
+/// assert!(compile("define LDC 0x0\nIR -> Foo\n".as_bytes()).is_err());
+/// ```
+pub fn compile<R: BufRead>(reader: R) -> Result<Firmware, FwcError> {
+    compile_firmware(reader, false)
+}
+
+/// Compile the firmware source from the given reader, taking the fetch phase
+/// from the lines before the first `define` instead of adding the built-in
+/// one.
+pub fn compile_explicit_fetch<R: BufRead>(reader: R) -> Result<Firmware, FwcError> {
+    compile_firmware(reader, true)
+}
+
+fn compile_firmware<R: BufRead>(reader: R, explicit_fetch: bool) -> Result<Firmware, FwcError> {
+    lazy_static! {
+        static ref DEFINE: Regex = Regex::new("^define ([A-Z]+) ((?:0[xbo]|\\$)?[A-Za-z0-9_]+)((?:\\s+[a-z-]+)*)$").unwrap();
+        static ref LABEL: Regex = Regex::new("^([A-Za-z_]\\w*):(.*)$").unwrap();
+        static ref GOTO: Regex = Regex::new("^goto\\s+(\\w+)$").unwrap();
+        static ref RAW: Regex = Regex::new("^raw\\s+(\\S+)$").unwrap();
+    }
+    let mut firmware = Firmware::new();
+    let mut memory = if explicit_fetch { Vec::new() } else { fetch_phase() };
+    // start of the current block, which is the fetch phase with explicit_fetch
+    let mut block_start = memory.len();
+    let mut in_fetch = explicit_fetch;
+    // labels and their microaddresses
+    let mut labels: HashMap<String, u8> = HashMap::new();
+    labels.insert("fetch".into(), 0x00);
+    labels.insert("decode".into(), 0xFF);
+    // cells that have an explicit goto: (cell, label, line number)
+    let mut gotos: Vec<(usize, String, usize)> = Vec::new();
+    // raw cells that set their own next address: (cell, address)
+    let mut raw_next: Vec<(usize, u8)> = Vec::new();
+    for (line_no, line) in reader.lines().enumerate() {
+        let line_no = line_no + 1;
+        let line = try!(line);
+        // strip comments starting with # or //
+        let comment_start = [line.find('#'), line.find("//")].iter()
+            .filter_map(|&i| i)
+            .min()
+            .unwrap_or(line.len());
+        let mut line = line[..comment_start].trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(cap) = LABEL.captures(line) {
+            let label = cap.at(1).unwrap();
+            if labels.contains_key(label) {
+                return Err(FwcError::DuplicateLabel(line_no, label.into()));
+            }
+            labels.insert(label.into(), memory.len() as u8);
+            line = cap.at(2).unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+        }
+        if let Some(cap) = DEFINE.captures(line) {
+            if in_fetch {
+                if memory.is_empty() {
+                    return Err(FwcError::MissingFetchPhase(line_no, cap[1].into()));
+                }
+                // continue with the decode phase
+                finish_block(&mut memory, block_start, 0xFF);
+                in_fetch = false;
+            } else {
+                // wrap back to fetch phase
+                finish_block(&mut memory, block_start, 0x00);
+            }
+            block_start = memory.len();
+
+            let opcode = match util::parse_num(&cap[2]) {
+                Some(opcode) => opcode as u8,
+                None => return Err(FwcError::InvalidOpcode(line_no, cap[2].into())),
+            };
+            if firmware.find_instruction(opcode).is_some() {
+                return Err(FwcError::DuplicateOpcode(line_no, opcode));
+            }
+            let mut kind = InstructionKind::from_mnemonic(&cap[1]);
+            let mut operand = OperandKind::from_opcode(opcode);
+            for word in cap[3].split_whitespace() {
+                if let Ok(k) = word.parse() {
+                    kind = k;
+                } else if let Ok(o) = word.parse() {
+                    operand = o;
+                } else {
+                    return Err(FwcError::UnknownKind(line_no, word.into()));
+                }
+            }
+            firmware.insert_instruction(Instruction {
+                opcode: opcode,
+                mnemonic: cap[1].into(),
+                start: memory.len() as u8,
+                kind: kind,
+                operand: operand,
+            });
+        } else {
+            let mut parts = Vec::new();
+            for part in line.split(';') {
+                if let Some(cap) = GOTO.captures(part.trim()) {
+                    gotos.push((memory.len(), cap[1].into(), line_no));
+                } else {
+                    parts.push(part);
+                }
+            }
+            let transfer = parts.join(";");
+            if let Some(cap) = RAW.captures(transfer.trim()) {
+                let word = match util::parse_num(&cap[1]) {
+                    Some(word) if word >= 0 && word as u32 <= masks::MICRO_DATA | masks::MICRO_NEXT
+                        => word as u32,
+                    _ => return Err(FwcError::InvalidRawWord(line_no, cap[1].into())),
+                };
+                if masks::micro_next(word) != 0 {
+                    raw_next.push((memory.len(), masks::micro_next(word)));
+                }
+                let next = (memory.len() + 1) as u8;
+                memory.push(if masks::micro_next(word) != 0 { word } else { masks::set_next(word, next) });
+                continue;
+            }
+            let result = if transfer.trim().is_empty() {
+                Ok(0)
+            } else {
+                parse_register_transfer(&transfer)
+            };
+            match result {
+                Ok(instr) => {
+                    let next = (memory.len() + 1) as u8;
+                    memory.push(instr | next as u32);
+                },
+                Err(e) => return Err(FwcError::ParseError(line_no, e, line.into())),
+            }
+        }
+    }
+    // finish last instruction
+    finish_block(&mut memory, block_start, if in_fetch { 0xFF } else { 0x00 });
+    // raw words keep their own next address, even at the end of a block
+    for (cell, next) in raw_next {
+        memory[cell] = masks::set_next(memory[cell], next);
+    }
+    // resolve the explicit jumps
+    for (cell, label, line_no) in gotos {
+        match labels.get(&label) {
+            Some(&target) => memory[cell] = masks::set_next(memory[cell], target),
+            None => return Err(FwcError::UndefinedLabel(line_no, label)),
+        }
+    }
+    firmware.load_memory(&memory);
+    Ok(firmware)
+}
+
+/// Turn a single microinstruction back into register transfer notation.
+///
+/// The address of the next microinstruction is not part of the result.
+pub fn decompile_microinstruction(instr: Microinstruction) -> String {
+    let decoded = DecodedMicroinstruction::decode(instr);
+    let is_set = |bit: Option<u32>| bit.map(|b| instr & b > 0).unwrap_or(false);
+    let mut parts = Vec::new();
+    for source in Register::all().iter().filter(|r| is_set(r.write_bit())) {
+        for target in Register::all().iter().filter(|r| is_set(r.read_bit())) {
+            parts.push(format!("{} -> {}", source, target));
+        }
+    }
+    if decoded.mem_read {
+        parts.push("R = 1".into());
+    }
+    if decoded.mem_write {
+        parts.push("W = 1".into());
+    }
+    // An empty line would be skipped, so the no-op has to be explicit
+    if decoded.alu != AluOp::Noop || parts.is_empty() {
+        parts.push(format!("ALU {}", decoded.alu));
+    }
+    parts.join("; ")
+}
+
+/// Turn the compiled firmware back into register transfer notation.
+///
+/// The fetch phase is omitted, as it is added by `compile`.
+pub fn decompile_firmware(firmware: &Firmware) -> String {
+    let mut output = String::new();
+    for instr in firmware.instructions() {
+        write!(output, "define {} {:#x}", instr.mnemonic, instr.opcode).unwrap();
+        if instr.kind != InstructionKind::from_mnemonic(&instr.mnemonic) {
+            write!(output, " {}", instr.kind).unwrap();
+        }
+        if instr.operand != OperandKind::from_opcode(instr.opcode) {
+            write!(output, " {}", instr.operand).unwrap();
+        }
+        writeln!(output, "").unwrap();
+        // Instructions with a special kind don't execute any code, their start
+        // is shared with the instruction defined after them. Normal
+        // instructions that share their start all run the same code, so it is
+        // written for each of them.
+        let mut address = instr.start;
+        let mut steps = 0;
+        while instr.kind == InstructionKind::Normal && address != 0 && steps <= 0xFF {
+            let micro = firmware.get_memory(address);
+            if micro == 0 {
+                break;
+            }
+            writeln!(output, "{}", decompile_microinstruction(micro)).unwrap();
+            address = DecodedMicroinstruction::decode(micro).next;
+            steps += 1;
+        }
+        writeln!(output, "").unwrap();
+    }
+    output
+}
@@ -5,9 +5,21 @@
 //! should look into the [`mimar-fwc`](../mimar_fwc/index.html) utiltiy for
 //! dealing with firmwares, the [`mimar-asm`](../mimar_asm/index.html) utility
 //! to assemble programs and the [`mimar-sim`](../mimar_sim/index.html)
-//! utility for simulation.
+//! utility for simulation. The assembler is also available as library, see
+//! the `asm` module.
+//!
+//! If the `serde` feature is enabled, the machine state (including the
+//! firmware) implements `Serialize` and `Deserialize`, so a running MIMA can be
+//! saved and restored later.
+
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[macro_use]
+extern crate lazy_static;
+extern crate regex;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::io::{self, BufRead};
 use std::error::Error;
 use std::fmt::{self, Formatter, Display};
@@ -17,13 +29,23 @@ pub mod util;
 pub mod firmware;
 pub mod registers;
 pub mod logger;
+pub mod asm;
+pub mod fwc;
 
-use self::firmware::Firmware;
-use self::registers::Register;
-use self::logger::Logger;
+use self::firmware::{Firmware, FirmwareLoadError, DecodedMicroinstruction, AluOp, InstructionKind, OperandKind};
+use self::registers::{Register, RegisterDescription};
+use self::logger::{Logger, NoLogging};
+
+/// Number of cycles a memory access takes.
+///
+/// The R or W bit has to be set for this many consecutive cycles. The data is
+/// transferred at the beginning of the cycle after that, so it can be used by
+/// the following microinstruction.
+pub const MEMORY_ACCESS_CYCLES: u8 = 3;
 
 /// State of the MIMA after a cycle completed
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum MimaState {
     /// The MIMA is well and running.
     Running,
@@ -31,10 +53,26 @@ pub enum MimaState {
     Error(MimaError),
     /// The MIMA has been halted.
     Halted,
+    /// A watched memory cell has been changed.
+    Watchpoint {
+        /// The address of the watched cell.
+        address: u32,
+        /// The value before the write.
+        old: u32,
+        /// The value after the write.
+        new: u32,
+    },
+    /// An instruction was fetched from an empty cell after the loaded program
+    /// (only with `trap_end_of_program`).
+    EndOfProgram {
+        /// The address of the empty cell.
+        address: u32,
+    },
 }
 
 /// Error that might happen during a MIMA cycle
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum MimaError {
     /// The bus is already busy
     BusBusy,
@@ -42,20 +80,38 @@ pub enum MimaError {
     BusEmpty,
     /// An invalid opcode was encountered
     InvalidOpcode,
+    /// The memory was accessed at an address wider than 20 bits (only in
+    /// strict mode)
+    MemoryAddressOutOfRange,
+    /// A normal instruction starts in unpopulated microcode, so it wouldn't do
+    /// anything
+    EmptyMicrocode,
 }
 
 /// Error that may arise when loading MIMA memory.
 #[derive(Debug)]
 pub enum MimaLoadError {
-    /// An invalid line was encountered.
-    InvalidLine,
+    /// An invalid line was encountered, with the 1-based line number.
+    InvalidLine(usize),
     /// Underlying IO error.
     IOError(io::Error),
+    /// The firmware couldn't be loaded (only from `Mima::load_program`).
+    InvalidFirmware(FirmwareLoadError),
+    /// The start location is neither a number nor a label of the program.
+    UnknownStart(String),
 }
 
 impl Display for MimaLoadError {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
-        write!(fmt, "{}", self.description())
+        match *self {
+            MimaLoadError::InvalidLine(line_no) =>
+                write!(fmt, "{} (line {})", self.description(), line_no),
+            MimaLoadError::IOError(_) => write!(fmt, "{}", self.description()),
+            MimaLoadError::InvalidFirmware(ref err) =>
+                write!(fmt, "{}: {}", self.description(), err),
+            MimaLoadError::UnknownStart(ref start) =>
+                write!(fmt, "{}: {}", self.description(), start),
+        }
     }
 }
 
@@ -65,17 +121,26 @@ impl From<io::Error> for MimaLoadError {
     }
 }
 
+impl From<FirmwareLoadError> for MimaLoadError {
+    fn from(err: FirmwareLoadError) -> MimaLoadError {
+        MimaLoadError::InvalidFirmware(err)
+    }
+}
+
 impl Error for MimaLoadError {
     fn description(&self) -> &'static str {
         match *self {
-            MimaLoadError::InvalidLine => "invalid input line",
+            MimaLoadError::InvalidLine(_) => "invalid input line",
             MimaLoadError::IOError(_) => "underlying IO error",
+            MimaLoadError::InvalidFirmware(_) => "invalid firmware",
+            MimaLoadError::UnknownStart(_) => "unknown start location",
         }
     }
 
     fn cause(&self) -> Option<&Error> {
         match *self {
             MimaLoadError::IOError(ref err) => Some(err),
+            MimaLoadError::InvalidFirmware(ref err) => Some(err),
             _ => None,
         }
     }
@@ -83,24 +148,115 @@ impl Error for MimaLoadError {
 
 /// A Mima with registers, memory and other state.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Mima {
     /// The main memory (RAM). Saved sparse, i.e. only cells with a different
-    /// value than 0.
-    pub memory: HashMap<u32, u32>,
+    /// value than 0, and ordered by address.
+    pub memory: BTreeMap<u32, u32>,
     /// The currently loaded firmware
     pub firmware: Firmware,
     /// The number of cycles the MIMA did.
     pub cycle_count: u64,
+    /// The number of cycles that did nothing but wait for memory, see
+    /// `memory_stall_cycles`.
+    memory_stall_cycles: u64,
     /// The values of the registers
+    #[cfg_attr(feature = "serde", serde(with = "register_map"))]
     pub registers: HashMap<Register, u32>,
+    /// Descriptions of the additional registers, see `add_register`.
+    pub custom_registers: Vec<RegisterDescription>,
     /// The next instruction which will be executed.
     pub next_instruction: u8,
     /// Mapping of labels to their address.
     pub labels: HashMap<String, u32>,
+    /// Addresses of memory cells that stop the execution when they change.
+    pub watchpoints: HashSet<u32>,
+    /// Address of the memory-mapped output device, if any.
+    pub output_address: Option<u32>,
+    /// Address of the memory-mapped input device, if any.
+    pub input_address: Option<u32>,
+    /// Values that are waiting to be read from the input device.
+    pub input: VecDeque<u32>,
+    /// Reject memory accesses to addresses wider than 20 bits with
+    /// `MimaError::MemoryAddressOutOfRange`, instead of accessing them.
+    pub strict_memory: bool,
+    /// Warn about writes to cells that have been executed as instructions.
+    pub warn_smc: bool,
+    /// Stop with `MimaState::EndOfProgram` instead of executing empty cells
+    /// after the highest address given to `load`.
+    pub trap_end_of_program: bool,
+    /// The highest address given to `load`.
+    highest_loaded: Option<u32>,
+    /// The firmware defines its own fetch phase (see `mimar-fwc
+    /// --explicit-fetch`), so the address of an instruction is taken from the
+    /// IAR at the start of the fetch phase, instead of assuming that the fetch
+    /// phase incremented the IAR by one.
+    pub explicit_fetch: bool,
+    /// The value of the IAR at the start of the last fetch phase.
+    fetch_address: u32,
+    /// Addresses of all executed instructions, only tracked with `warn_smc`.
+    fetched: HashSet<u32>,
+    /// Whether the last addition of the ALU overflowed
+    add_overflow: bool,
+    /// The value on the bus during the last cycle
+    bus_value: Option<u32>,
     /// Setting of the R/W memory bits
     rw_bits: u32,
     /// Countdown until the memory access is complete
     memory_timer: u8,
+    /// Number of cycles that can be reverted with `step_back`. The history is
+    /// disabled with 0, which is the default.
+    pub history_depth: usize,
+    /// Changes of the last cycles, the most recent one last.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    history: VecDeque<CycleDelta>,
+}
+
+/// Serialization of the register values as a list of pairs, as custom
+/// registers can't be used as keys in formats like JSON.
+#[cfg(feature = "serde")]
+mod register_map {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::registers::Register;
+
+    pub fn serialize<S: Serializer>(registers: &HashMap<Register, u32>, serializer: S)
+                                    -> Result<S::Ok, S::Error> {
+        let mut pairs = registers.iter().map(|(&r, &v)| (r, v)).collect::<Vec<_>>();
+        pairs.sort_by_key(|&(r, _)| r.to_string());
+        pairs.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D)
+                                                  -> Result<HashMap<Register, u32>, D::Error> {
+        let pairs: Vec<(Register, u32)> = try!(Deserialize::deserialize(deserializer));
+        Ok(pairs.into_iter().collect())
+    }
+}
+
+/// The state a cycle changed, used to revert the cycle.
+///
+/// Only the old values of things that actually changed are saved, so a delta
+/// is much smaller than a copy of the whole MIMA.
+#[derive(Clone, Debug)]
+struct CycleDelta {
+    /// Old values of the changed registers.
+    registers: Vec<(Register, u32)>,
+    /// Address and old value of the written memory cell.
+    memory: Option<(u32, u32)>,
+    /// Value that was taken from the input device.
+    input: Option<u32>,
+    /// Address that was newly added to the fetched addresses.
+    fetched: Option<u32>,
+    next_instruction: u8,
+    fetch_address: u32,
+    memory_stall_cycles: u64,
+    add_overflow: bool,
+    bus_value: Option<u32>,
+    rw_bits: u32,
+    memory_timer: u8,
 }
 
 impl Default for Mima {
@@ -109,11 +265,77 @@ impl Default for Mima {
     }
 }
 
+/// Builder to set up a MIMA.
+///
+/// Cells set with `set_cell` are applied after the memory has been loaded, so
+/// they override the loaded values regardless of the order of the calls.
+///
+/// ```rust
+/// use mimar::Mima;
+/// use mimar::firmware::Firmware;
+/// use mimar::registers::Register;
+/// let m = Mima::builder()
+///     .firmware(Firmware::new())
+///     .memory_from_str("0x10 0x5 ;A\n").unwrap()
+///     .set_cell(0x11, 7)
+///     .start_at(0x100)
+///     .build();
+/// assert_eq!(m.get_memory(0x10), 5);
+/// assert_eq!(m.get_memory(0x11), 7);
+/// assert_eq!(m.registers[&Register::IAR], 0x100);
+/// ```
+#[derive(Debug, Default)]
+pub struct MimaBuilder {
+    mima: Mima,
+    cells: Vec<(u32, u32)>,
+    start: Option<u32>,
+}
+
+impl MimaBuilder {
+    /// Use the given firmware.
+    pub fn firmware(mut self, firmware: Firmware) -> MimaBuilder {
+        self.mima.firmware = firmware;
+        self
+    }
+
+    /// Load memory and labels from the given string.
+    ///
+    /// See `Mima::load` for the format.
+    pub fn memory_from_str(mut self, text: &str) -> Result<MimaBuilder, MimaLoadError> {
+        try!(self.mima.load_from_str(text));
+        Ok(self)
+    }
+
+    /// Set the given memory cell to the given value.
+    pub fn set_cell(mut self, address: u32, value: u32) -> MimaBuilder {
+        self.cells.push((address, value));
+        self
+    }
+
+    /// Start the execution at the given address.
+    pub fn start_at(mut self, address: u32) -> MimaBuilder {
+        self.start = Some(address);
+        self
+    }
+
+    /// Build the MIMA.
+    pub fn build(self) -> Mima {
+        let mut mima = self.mima;
+        for (address, value) in self.cells {
+            mima.set_memory(address, value);
+        }
+        if let Some(address) = self.start {
+            mima.jump(address);
+        }
+        mima
+    }
+}
+
 macro_rules! mtry {
-    ($expr:expr) => {
+    ($expr:expr, $line_no:expr) => {
         match $expr {
             Some(value) => value,
-            None => return Err(MimaLoadError::InvalidLine),
+            None => return Err(MimaLoadError::InvalidLine($line_no + 1)),
         }
     }
 }
@@ -138,6 +360,16 @@ macro_rules! bus_read {
     }
 }
 
+/// Extract the opcode of the given command, handling extended opcodes.
+fn opcode(word: u32) -> u8 {
+    let opcode = (word & masks::OPCODE) >> masks::OPCODE_SHIFT;
+    if opcode == 0xF {
+        ((word & masks::EXTENDED) >> masks::EXTENDED_SHIFT) as u8
+    } else {
+        opcode as u8
+    }
+}
+
 impl Mima {
     /// Create a new MIMA.
     ///
@@ -150,22 +382,239 @@ impl Mima {
         }
         registers.insert(Register::One, 1);
         Mima {
-            memory: HashMap::new(),
+            memory: BTreeMap::new(),
             firmware: Firmware::new(),
             cycle_count: 0,
+            memory_stall_cycles: 0,
             registers: registers,
+            custom_registers: Vec::new(),
             next_instruction: 0,
             labels: HashMap::new(),
+            watchpoints: HashSet::new(),
+            output_address: None,
+            input_address: None,
+            input: VecDeque::new(),
+            strict_memory: false,
+            warn_smc: false,
+            trap_end_of_program: false,
+            highest_loaded: None,
+            explicit_fetch: false,
+            fetch_address: 0,
+            fetched: HashSet::new(),
+            add_overflow: false,
+            bus_value: None,
             rw_bits: 0,
             memory_timer: 0,
+            history_depth: 0,
+            history: VecDeque::new(),
         }
     }
 
+    /// Create a builder to set up a MIMA.
+    pub fn builder() -> MimaBuilder {
+        MimaBuilder::default()
+    }
+
+    /// Create a MIMA with the firmware and the program from the given readers.
+    ///
+    /// The firmware is read with `Firmware::load`, the program with `load`.
+    /// If `start` is given, the program starts at this location, which can be
+    /// an address or a label of the program.
+    ///
+    /// ```rust
+    /// use mimar::{Mima, MimaState};
+    /// let firmware = include_str!("default.mimafw");
+    /// let program = "0x10 0x0 ;START\n0x11 0x000007\n0x12 0xF00000\n";
+    /// let mut m = Mima::load_program(firmware.as_bytes(), program.as_bytes(), Some("START"))
+    ///     .unwrap();
+    /// assert_eq!(m.iar(), 0x10);
+    /// assert_eq!(m.run_fast(1000), MimaState::Halted);
+    /// assert_eq!(m.accu(), 7);
+    /// assert!(Mima::load_program(firmware.as_bytes(), program.as_bytes(), Some("END")).is_err());
+    /// ```
+    pub fn load_program<R1: BufRead, R2: BufRead>(firmware: R1, program: R2, start: Option<&str>)
+                                                  -> Result<Mima, MimaLoadError> {
+        let mut mima = Mima::new();
+        mima.firmware = try!(Firmware::load(firmware));
+        try!(mima.load(program));
+        if let Some(start) = start {
+            match mima.resolve(start) {
+                Some(address) => mima.jump(address),
+                None => return Err(MimaLoadError::UnknownStart(start.into())),
+            }
+        }
+        Ok(mima)
+    }
+
     /// Set the given register to the given value.
     ///
-    /// The value is automatically truncated.
+    /// The value is automatically truncated. Writes to custom registers that
+    /// were never added are ignored.
+    ///
+    /// ```rust
+    /// use mimar::Mima;
+    /// use mimar::registers::Register;
+    /// let mut m = Mima::new();
+    /// m.set_register(Register::Accu, 0x1234567);
+    /// assert_eq!(m.accu(), 0x234567);
+    /// m.set_register(Register::Custom(3), 5);
+    /// assert_eq!(m.get_register(Register::Custom(3)), 0);
+    /// ```
     pub fn set_register(&mut self, reg: Register, value: u32) {
-        self.registers.insert(reg, value & reg.value_bits());
+        if let Register::Custom(i) = reg {
+            if self.custom_register(i).is_none() {
+                return;
+            }
+        }
+        let mask = util::mask(self.register_width(reg) as u32);
+        self.registers.insert(reg, value & mask);
+    }
+
+    /// Return the value of the given register.
+    ///
+    /// Registers that have never been set read as 0.
+    ///
+    /// ```rust
+    /// use mimar::Mima;
+    /// use mimar::registers::Register;
+    /// let mut m = Mima::new();
+    /// m.set_register(Register::Accu, 42);
+    /// assert_eq!(m.get_register(Register::Accu), 42);
+    /// assert_eq!(m.accu(), 42);
+    /// assert_eq!(m.get_register(Register::Custom(3)), 0);
+    /// ```
+    pub fn get_register(&self, reg: Register) -> u32 {
+        self.registers.get(&reg).cloned().unwrap_or(0)
+    }
+
+    /// Return the value of the accumulator.
+    pub fn accu(&self) -> u32 {
+        self.get_register(Register::Accu)
+    }
+
+    /// Return the value of the instruction address register.
+    pub fn iar(&self) -> u32 {
+        self.get_register(Register::IAR)
+    }
+
+    /// Return the value of the instruction register.
+    pub fn ir(&self) -> u32 {
+        self.get_register(Register::IR)
+    }
+
+    /// Return the value of the first ALU input register.
+    pub fn x(&self) -> u32 {
+        self.get_register(Register::X)
+    }
+
+    /// Return the value of the second ALU input register.
+    pub fn y(&self) -> u32 {
+        self.get_register(Register::Y)
+    }
+
+    /// Return the value of the ALU result register.
+    pub fn z(&self) -> u32 {
+        self.get_register(Register::Z)
+    }
+
+    /// Return the value of the storage address register.
+    pub fn sar(&self) -> u32 {
+        self.get_register(Register::SAR)
+    }
+
+    /// Return the value of the storage data register.
+    pub fn sdr(&self) -> u32 {
+        self.get_register(Register::SDR)
+    }
+
+    /// Add a register and return its handle.
+    ///
+    /// The register is initialized to 0 and takes part in the bus transfers
+    /// according to its control bits, just like the stock registers. At most
+    /// 256 registers can be added, after that `None` is returned.
+    ///
+    /// ```rust
+    /// use mimar::Mima;
+    /// use mimar::logger::NoLogging;
+    /// use mimar::masks::ACCU_WRITE;
+    /// use mimar::registers::{Register, RegisterDescription};
+    /// let mut m = Mima::new();
+    /// let r0 = m.add_register(RegisterDescription {
+    ///     name: "R0".into(),
+    ///     width: 24,
+    ///     read_bit: Some(1 << 8),
+    ///     write_bit: Some(1 << 9),
+    /// }).unwrap();
+    /// // copy the accumulator to R0
+    /// m.firmware.load_memory(&[ACCU_WRITE | 1 << 8 | 0x01]);
+    /// m.set_register(Register::Accu, 5);
+    /// m.cycle(&NoLogging);
+    /// assert_eq!(m.registers[&r0], 5);
+    /// assert_eq!(m.register_name(r0), "R0");
+    /// ```
+    pub fn add_register(&mut self, description: RegisterDescription) -> Option<Register> {
+        if self.custom_registers.len() > u8::MAX as usize {
+            return None;
+        }
+        let register = Register::Custom(self.custom_registers.len() as u8);
+        self.custom_registers.push(description);
+        self.registers.insert(register, 0);
+        Some(register)
+    }
+
+    /// Return the description of the given custom register, if it was added.
+    fn custom_register(&self, index: u8) -> Option<&RegisterDescription> {
+        self.custom_registers.get(index as usize)
+    }
+
+    /// Return the name of the given register.
+    ///
+    /// Custom registers that were never added are named like
+    /// `Register::to_string` does.
+    pub fn register_name(&self, register: Register) -> String {
+        match register {
+            Register::Custom(i) => {
+                self.custom_register(i).map(|d| d.name.clone()).unwrap_or_else(|| register.to_string())
+            },
+            _ => register.to_string(),
+        }
+    }
+
+    /// Return the width of the given register, including custom registers.
+    ///
+    /// Custom registers that were never added have the width given by
+    /// `Register::width`.
+    pub fn register_width(&self, register: Register) -> u8 {
+        match register {
+            Register::Custom(i) => self.custom_register(i).map(|d| d.width).unwrap_or(register.width()),
+            _ => register.width(),
+        }
+    }
+
+    /// Return the control bits of the given register, including custom
+    /// registers.
+    ///
+    /// See `Register::control_bits` for the format.
+    pub fn register_control_bits(&self, register: Register) -> (Option<u32>, Option<u32>) {
+        (self.register_read_bit(register), self.register_write_bit(register))
+    }
+
+    /// Return the bit that lets the given register read from the bus,
+    /// including custom registers.
+    pub fn register_read_bit(&self, register: Register) -> Option<u32> {
+        match register {
+            Register::Custom(i) => self.custom_register(i).and_then(|d| d.read_bit),
+            _ => register.read_bit(),
+        }
+    }
+
+    /// Return the bit that lets the given register write to the bus,
+    /// including custom registers.
+    pub fn register_write_bit(&self, register: Register) -> Option<u32> {
+        match register {
+            Register::Custom(i) => self.custom_register(i).and_then(|d| d.write_bit),
+            _ => register.write_bit(),
+        }
     }
 
     /// Get the memory at the given location
@@ -173,14 +622,193 @@ impl Mima {
         *self.memory.get(&location).unwrap_or(&0)
     }
 
+    /// Iterate over all nonzero cells as `(address, value)`, sorted by address.
+    ///
+    /// ```rust
+    /// use mimar::Mima;
+    /// let mut m = Mima::new();
+    /// m.set_memory(0x20, 2);
+    /// m.set_memory(0x10, 1);
+    /// m.set_memory(0x30, 0);
+    /// assert_eq!(m.memory_iter().collect::<Vec<_>>(), vec![(0x10, 1), (0x20, 2)]);
+    /// ```
+    pub fn memory_iter<'a>(&'a self) -> impl Iterator<Item=(u32, u32)> + 'a {
+        self.memory.iter().map(|(&address, &value)| (address, value))
+    }
+
     /// Set the given memory address to the given value.
     pub fn set_memory(&mut self, location: u32, value: u32) {
-        self.memory.remove(&location);
         if value != 0 {
             self.memory.insert(location, value);
+        } else {
+            self.memory.remove(&location);
         }
     }
 
+    /// Set consecutive memory cells, starting at the given address.
+    ///
+    /// ```rust
+    /// use mimar::Mima;
+    /// let mut m = Mima::new();
+    /// m.set_memory(0x11, 9);
+    /// m.set_memory_block(0x10, &[1, 0, 3]);
+    /// assert_eq!(m.get_memory(0x10), 1);
+    /// assert_eq!(m.get_memory(0x11), 0);
+    /// assert_eq!(m.get_memory(0x12), 3);
+    /// assert_eq!(m.memory.len(), 2);
+    /// ```
+    pub fn set_memory_block(&mut self, start: u32, values: &[u32]) {
+        for (address, &value) in (start..).zip(values) {
+            self.set_memory(address, value);
+        }
+    }
+
+    /// Watch the memory cell at the given location.
+    ///
+    /// If the cell is changed to a different value during a cycle, the cycle
+    /// returns `MimaState::Watchpoint`.
+    pub fn add_watchpoint(&mut self, address: u32) {
+        self.watchpoints.insert(address);
+    }
+
+    /// Stop watching the memory cell at the given location.
+    pub fn remove_watchpoint(&mut self, address: u32) {
+        self.watchpoints.remove(&address);
+    }
+
+    /// Map the output device to the given address.
+    ///
+    /// When the MIMA writes to this address, the low byte of the value is
+    /// passed to `Logger::log_output`. The value is stored in memory as well.
+    pub fn set_output_address(&mut self, address: u32) {
+        self.output_address = Some(address);
+    }
+
+    /// Map the input device to the given address.
+    ///
+    /// When the MIMA reads from this address, the next value from the input
+    /// queue is returned instead of the memory content. If the queue is empty,
+    /// 0 is returned.
+    pub fn set_input_address(&mut self, address: u32) {
+        self.input_address = Some(address);
+    }
+
+    /// Append a value to the input queue.
+    pub fn push_input(&mut self, value: u32) {
+        self.input.push_back(value);
+    }
+
+    /// Return true if the last addition of the ALU overflowed, i.e. the sum
+    /// didn't fit into 24 bits and has been truncated.
+    ///
+    /// The flag is only updated by additions, so other ALU operations keep it.
+    ///
+    /// ```rust
+    /// use mimar::Mima;
+    /// use mimar::logger::NoLogging;
+    /// use mimar::masks::*;
+    /// use mimar::registers::Register;
+    /// let mut m = Mima::new();
+    /// m.firmware.load_memory(&[ALU_C0 | 0x01, ALU_C0 | 0x01]);
+    /// m.set_register(Register::X, 0xFFFFFF);
+    /// m.set_register(Register::Y, 1);
+    /// m.cycle(&NoLogging);
+    /// assert!(m.last_add_overflowed());
+    /// assert_eq!(m.z(), 0);
+    /// m.set_register(Register::Y, 0);
+    /// m.cycle(&NoLogging);
+    /// assert!(!m.last_add_overflowed());
+    /// ```
+    pub fn last_add_overflowed(&self) -> bool {
+        self.add_overflow
+    }
+
+    /// Return the value that was driven onto the bus during the last cycle.
+    ///
+    /// `None` is returned if no register wrote to the bus.
+    ///
+    /// ```rust
+    /// use mimar::Mima;
+    /// use mimar::logger::NoLogging;
+    /// use mimar::masks::*;
+    /// use mimar::registers::Register;
+    /// let mut m = Mima::new();
+    /// m.firmware.load_memory(&[ACCU_WRITE | X_READ | 0x01, ALU_C0 | 0x02]);
+    /// m.set_register(Register::Accu, 42);
+    /// m.cycle(&NoLogging);
+    /// assert_eq!(m.last_bus_value(), Some(42));
+    /// m.cycle(&NoLogging);
+    /// assert_eq!(m.last_bus_value(), None);
+    /// ```
+    pub fn last_bus_value(&self) -> Option<u32> {
+        self.bus_value
+    }
+
+    /// Return true if a memory read or write is in progress.
+    pub fn memory_pending(&self) -> bool {
+        masks::mem_access(self.rw_bits) > 0
+    }
+
+    /// Return the number of cycles until the current memory access completes.
+    pub fn memory_timer(&self) -> u8 {
+        self.memory_timer
+    }
+
+    /// Return the number of cycles that were memory stalls.
+    ///
+    /// A stall is a microinstruction that only keeps the R or W bit set, so it
+    /// does nothing but wait for the memory access to complete. Memory
+    /// accesses that overlap with other work (like incrementing the IAR during
+    /// the fetch phase) don't count.
+    ///
+    /// ```rust
+    /// use mimar::Mima;
+    /// use mimar::firmware::stock_firmware;
+    /// let mut m = Mima::builder()
+    ///     .firmware(stock_firmware())
+    ///     .memory_from_str("0x0 0x100001 # LDV 1\n0x1 0x5\n0x2 0xF00000\n")
+    ///     .unwrap()
+    ///     .build();
+    /// m.run_fast(1000);
+    /// assert!(m.memory_stall_cycles() > 0);
+    /// assert!(m.memory_stall_cycles() < m.cycle_count);
+    /// ```
+    pub fn memory_stall_cycles(&self) -> u64 {
+        self.memory_stall_cycles
+    }
+
+    /// Resolve the given target, which may be an address or a label.
+    ///
+    /// ```rust
+    /// use mimar::Mima;
+    /// let mut m = Mima::new();
+    /// m.load_from_str("0x10 0x0 ;PTR\n0x20 0x5 ;TARGET\n").unwrap();
+    /// assert_eq!(m.resolve("TARGET"), Some(0x20));
+    /// assert_eq!(m.resolve("0x30"), Some(0x30));
+    /// assert_eq!(m.resolve("MISSING"), None);
+    /// ```
+    pub fn resolve(&self, target: &str) -> Option<u32> {
+        util::parse_num(target)
+            .map(|v| v as u32)
+            .or_else(|| self.labels.get(target).cloned())
+    }
+
+    /// Return all labels with their addresses, sorted by address and name.
+    ///
+    /// ```rust
+    /// use mimar::Mima;
+    /// let mut m = Mima::new();
+    /// m.load_from_str("0x20 0x1 ;B A\n0x10 0x2 ;C\n").unwrap();
+    /// assert_eq!(m.labels_sorted(), vec![("C", 0x10), ("A", 0x20), ("B", 0x20)]);
+    /// ```
+    pub fn labels_sorted(&self) -> Vec<(&str, u32)> {
+        let mut labels = self.labels.iter()
+            .map(|(label, &address)| (label as &str, address))
+            .collect::<Vec<_>>();
+        labels.sort_by(|a, b| (a.1, a.0).cmp(&(b.1, b.0)));
+        labels
+    }
+
     /// Get all labels pointing to the given location
     pub fn find_labels(&self, location: u32) -> Vec<&str> {
         self.labels.iter()
@@ -190,98 +818,390 @@ impl Mima {
     }
 
     /// Advance the MIMA by a cycle and update the internal state.
+    ///
+    /// All output is passed to the given logger, see the `logger` module.
+    ///
+    /// ```rust
+    /// use mimar::{Mima, MimaState};
+    /// use mimar::logger::NoLogging;
+    /// use mimar::masks::*;
+    /// use mimar::registers::Register;
+    /// let mut m = Mima::new();
+    /// m.firmware.load_memory(&[ONE_WRITE | ACCU_READ | 0x01, 0x01]);
+    /// assert_eq!(m.cycle(&NoLogging), MimaState::Running);
+    /// assert_eq!(m.accu(), 1);
+    /// assert_eq!(m.cycle_count, 1);
+    /// ```
     pub fn cycle<L: Logger>(&mut self, log: &L) -> MimaState {
+        if self.history_depth == 0 {
+            self.history.clear();
+            return self.cycle_inner(log);
+        }
+        let registers = self.registers.iter().map(|(&r, &v)| (r, v)).collect::<Vec<_>>();
+        // Memory is only written at the start of a cycle, at the address in
+        // the SAR. Saving the cell if no write happens is harmless.
+        let memory = if self.rw_bits & masks::MEM_WRITE > 0 && self.memory_timer == 0 {
+            let address = self.sar();
+            Some((address, self.get_memory(address)))
+        } else {
+            None
+        };
+        let input = self.input.front().cloned();
+        let input_len = self.input.len();
+        let fetched_len = self.fetched.len();
+        let instruction_address = self.instruction_address();
+        let mut delta = CycleDelta {
+            registers: Vec::new(),
+            memory: memory,
+            input: None,
+            fetched: None,
+            next_instruction: self.next_instruction,
+            fetch_address: self.fetch_address,
+            memory_stall_cycles: self.memory_stall_cycles,
+            add_overflow: self.add_overflow,
+            bus_value: self.bus_value,
+            rw_bits: self.rw_bits,
+            memory_timer: self.memory_timer,
+        };
+
+        let state = self.cycle_inner(log);
+
+        delta.registers = registers.into_iter()
+            .filter(|&(r, v)| self.registers.get(&r) != Some(&v))
+            .collect();
+        if self.input.len() < input_len {
+            delta.input = input;
+        }
+        if self.fetched.len() > fetched_len {
+            delta.fetched = Some(instruction_address);
+        }
+        while self.history.len() >= self.history_depth {
+            self.history.pop_front();
+        }
+        self.history.push_back(delta);
+        state
+    }
+
+    /// Revert the most recent cycle.
+    ///
+    /// This only works if the history is enabled by setting `history_depth`
+    /// to the number of cycles that should be kept. Registers, memory, the
+    /// input and the progress of the microprogram are restored, but output
+    /// that has been written can't be taken back. Returns `false` if there is
+    /// no cycle left to revert.
+    ///
+    /// ```rust
+    /// use mimar::Mima;
+    /// use mimar::firmware::stock_firmware;
+    /// let mut m = Mima::builder()
+    ///     .firmware(stock_firmware())
+    ///     .memory_from_str("0x0 0x300001 # ADD 1\n0x1 0x5\n0x2 0xF00000\n")
+    ///     .unwrap()
+    ///     .build();
+    /// m.history_depth = 100;
+    /// m.run_fast(1000);
+    /// assert_eq!(m.accu(), 5);
+    /// while m.step_back() {}
+    /// assert_eq!(m.accu(), 0);
+    /// assert_eq!(m.iar(), 0);
+    /// assert_eq!(m.cycle_count, 0);
+    /// ```
+    pub fn step_back(&mut self) -> bool {
+        let delta = match self.history.pop_back() {
+            Some(delta) => delta,
+            None => return false,
+        };
+        self.cycle_count -= 1;
+        for (register, value) in delta.registers {
+            self.registers.insert(register, value);
+        }
+        if let Some((address, value)) = delta.memory {
+            self.set_memory(address, value);
+        }
+        if let Some(value) = delta.input {
+            self.input.push_front(value);
+        }
+        if let Some(address) = delta.fetched {
+            self.fetched.remove(&address);
+        }
+        self.next_instruction = delta.next_instruction;
+        self.fetch_address = delta.fetch_address;
+        self.memory_stall_cycles = delta.memory_stall_cycles;
+        self.add_overflow = delta.add_overflow;
+        self.bus_value = delta.bus_value;
+        self.rw_bits = delta.rw_bits;
+        self.memory_timer = delta.memory_timer;
+        true
+    }
+
+    /// Revert the most recent instruction.
+    ///
+    /// Cycles are reverted until the MIMA is at the start of a fetch phase
+    /// again, or the history is exhausted. Returns `false` if no cycle could
+    /// be reverted. See `step_back` for the limitations.
+    pub fn step_back_instruction(&mut self) -> bool {
+        if !self.step_back() {
+            return false;
+        }
+        while self.next_instruction != 0 && self.step_back() {}
+        true
+    }
+
+    /// Return the number of cycles that can currently be reverted.
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Address of the instruction that is currently decoded.
+    fn instruction_address(&self) -> u32 {
+        if self.explicit_fetch {
+            self.fetch_address
+        } else {
+            self.iar().wrapping_sub(1)
+        }
+    }
+
+    fn cycle_inner<L: Logger>(&mut self, log: &L) -> MimaState {
         self.cycle_count += 1;
+        self.bus_value = None;
         // The decoding phase is hard-coded
         if self.next_instruction == 0xFF {
-            let ir = self.registers[&Register::IR];
-            let mut opcode = (ir & masks::OPCODE) >> masks::OPCODE_SHIFT;
-            if opcode == 0xF {
-                opcode = (ir & masks::EXTENDED) >> masks::EXTENDED_SHIFT;
+            let ir = self.ir();
+            let iar = self.instruction_address();
+            if self.trap_end_of_program && self.highest_loaded.map_or(false, |h| iar > h) &&
+               !self.memory.contains_key(&iar) {
+                return MimaState::EndOfProgram { address: iar };
             }
-            let instruction = match self.firmware.find_instruction(opcode as u8) {
-                Some(i) => i.clone(),
-                None => return MimaState::Error(MimaError::InvalidOpcode),
+            // Only keep the parts we need, so the instruction doesn't have to
+            // be cloned
+            let (start, kind) = {
+                let instruction = match self.firmware.find_instruction(opcode(ir)) {
+                    Some(i) => i,
+                    None => return MimaState::Error(MimaError::InvalidOpcode),
+                };
+                let mut param = ir & masks::ADDRESS_MASK;
+                if instruction.opcode > 0xF {
+                    param &= !masks::EXTENDED;
+                }
+                log.log_instruction(&self, iar, instruction, param);
+                (instruction.start, instruction.kind)
             };
-            let mut param = ir & masks::ADDRESS_MASK;
-            if instruction.opcode > 0xF {
-                param &= !masks::EXTENDED;
+            // An instruction starting in unpopulated microcode would jump back
+            // to the fetch phase without doing anything
+            if kind == InstructionKind::Normal && self.firmware.get_memory(start) == 0 {
+                return MimaState::Error(MimaError::EmptyMicrocode);
             }
-            log.log_instruction(&self,
-                                self.registers[&Register::IAR] - 1,
-                                &instruction,
-                                param);
-            self.next_instruction = instruction.start;
-            // Hard-coded HALT instruction
-            if instruction.mnemonic == "HALT" {
-                return MimaState::Halted;
-            // Hard-coded JMN instruction
-            } else if instruction.mnemonic == "JMN" {
-                if self.registers[&Register::Accu] > 0x7FFFFF {
-                    self.set_register(Register::IAR, ir);
-                }
-                self.next_instruction = 0x00;
+            if self.warn_smc {
+                self.fetched.insert(iar);
+            }
+            self.next_instruction = start;
+            match kind {
+                InstructionKind::Normal => (),
+                InstructionKind::Halt => return MimaState::Halted,
+                InstructionKind::ConditionalJumpNegative => {
+                    if util::is_negative(self.accu(), Register::Accu.width()) {
+                        self.set_register(Register::IAR, ir);
+                    }
+                    self.next_instruction = 0x00;
+                },
             }
             return MimaState::Running;
         }
 
+        if self.next_instruction == 0x00 {
+            self.fetch_address = self.iar();
+        }
         let instr = self.firmware.get_memory(self.next_instruction);
-        self.next_instruction = (instr & masks::MICRO_NEXT) as u8;
+        log.log_microstep(&self, self.next_instruction, instr);
+        if masks::mem_access(instr) > 0 && instr & masks::MICRO_DATA & !masks::MEM_ACCESS == 0 {
+            self.memory_stall_cycles += 1;
+        }
+        let decoded = DecodedMicroinstruction::decode(instr);
+        self.next_instruction = decoded.next;
         let mut bus: Option<u32> = None;
+        let mut state = MimaState::Running;
+
+        if self.strict_memory && masks::mem_access(self.rw_bits) > 0 && self.memory_timer == 0 &&
+           self.sar() > masks::ADDRESS_MASK {
+            return MimaState::Error(MimaError::MemoryAddressOutOfRange);
+        }
 
         if self.rw_bits & masks::MEM_READ > 0 && self.memory_timer == 0 {
-            let address = self.registers[&Register::SAR];
-            let data = self.get_memory(address);
+            let address = self.sar();
+            let data = if self.input_address == Some(address) {
+                self.input.pop_front().unwrap_or(0)
+            } else {
+                self.get_memory(address)
+            };
             self.set_register(Register::SDR, data);
         } else if self.rw_bits & masks::MEM_WRITE > 0 && self.memory_timer == 0 {
-            let address = self.registers[&Register::SAR];
-            let data = self.registers[&Register::SDR];
+            let address = self.sar();
+            let data = self.sdr();
+            let old = self.get_memory(address);
             self.set_memory(address, data);
+            if self.output_address == Some(address) {
+                log.log_output(data as u8);
+            }
+            if self.warn_smc && self.fetched.contains(&address) {
+                log.log_warning(&self, &format!("self-modifying code: write to {:#07x}", address));
+            }
+            if old != data && self.watchpoints.contains(&address) {
+                state = MimaState::Watchpoint {
+                    address: address,
+                    old: old,
+                    new: data,
+                };
+            }
         }
 
-        if self.rw_bits & masks::MEM_ACCESS == instr & masks::MEM_ACCESS  && self.memory_timer > 0 {
+        if masks::mem_access(self.rw_bits) == masks::mem_access(instr) && self.memory_timer > 0 {
             self.memory_timer -= 1;
         } else {
-            self.memory_timer = 2;
+            self.memory_timer = MEMORY_ACCESS_CYCLES - 1;
         }
 
-        self.rw_bits = instr & masks::MEM_ACCESS;
+        self.rw_bits = masks::mem_access(instr);
 
         for (&register, &value) in &self.registers {
-            if let (_, Some(pin)) = register.control_bits() {
+            if let Some(pin) = self.register_write_bit(register) {
                 if instr & pin > 0 {
                     bus_write!(bus, value);
                 }
             }
         }
 
-        for (&register, value) in self.registers.iter_mut() {
-            if let (Some(pin), _) = register.control_bits() {
+        self.bus_value = bus;
+
+        let custom = (0..self.custom_registers.len()).map(|i| Register::Custom(i as u8));
+        for register in Register::all().iter().cloned().chain(custom) {
+            if let Some(pin) = self.register_read_bit(register) {
                 if instr & pin > 0 {
                     let data = bus_read!(bus);
-                    *value = data & register.value_bits();
+                    self.set_register(register, data);
                 }
             }
         }
 
-        let alu_cmd = (instr & masks::ALU_CONTROL) >> masks::ALU_SHIFT;
-        let (reg_x, reg_y) = (self.registers[&Register::X], self.registers[&Register::Y]);
-        match alu_cmd {
-            0 => (),
-            1 => self.set_register(Register::Z, reg_x + reg_y),
-            2 => self.set_register(Register::Z, util::rar(reg_x, 24)),
-            3 => self.set_register(Register::Z, reg_x & reg_y),
-            4 => self.set_register(Register::Z, reg_x | reg_y),
-            5 => self.set_register(Register::Z, reg_x ^ reg_y),
-            6 => self.set_register(Register::Z, !reg_x),
-            7 => self.set_register(Register::Z,
-                                   if reg_x == reg_y {0xFFFFFF} else { 0 }),
-            _ => unreachable!(),
+        let (reg_x, reg_y) = (self.x(), self.y());
+        match decoded.alu {
+            AluOp::Noop => (),
+            AluOp::Add => {
+                self.add_overflow = reg_x + reg_y > masks::DATA_MASK;
+                self.set_register(Register::Z, reg_x + reg_y);
+            },
+            AluOp::Rar => self.set_register(Register::Z, util::rar(reg_x, 24)),
+            AluOp::And => self.set_register(Register::Z, reg_x & reg_y),
+            AluOp::Or => self.set_register(Register::Z, reg_x | reg_y),
+            AluOp::Xor => self.set_register(Register::Z, reg_x ^ reg_y),
+            AluOp::Not => self.set_register(Register::Z, !reg_x),
+            AluOp::Eql => self.set_register(Register::Z,
+                                            if reg_x == reg_y {0xFFFFFF} else { 0 }),
         }
+        if decoded.alu != AluOp::Noop {
+            log.log_alu(&self, decoded.alu, reg_x, reg_y, self.z());
+        }
+
+        state
+    }
 
+    /// Advance the MIMA by a cycle without any logging.
+    ///
+    /// This is the same as `cycle(&NoLogging)`.
+    pub fn cycle_silent(&mut self) -> MimaState {
+        self.cycle(&NoLogging)
+    }
+
+    /// Run the MIMA without logging until it stops or `max_cycles` cycles have
+    /// been executed.
+    ///
+    /// If the history is disabled (`history_depth` is 0), the cycles are run
+    /// without any history bookkeeping. Otherwise this is the same as calling
+    /// `cycle(&NoLogging)` in a loop, so the history is recorded as usual.
+    /// `MimaState::Running` is returned if the MIMA didn't stop in time.
+    ///
+    /// ```rust
+    /// use mimar::{Mima, MimaState};
+    /// use mimar::firmware::stock_firmware;
+    /// let mut m = Mima::builder()
+    ///     .firmware(stock_firmware())
+    ///     .memory_from_str("0x0 0x800000 # JMP 0\n")
+    ///     .unwrap()
+    ///     .build();
+    /// assert_eq!(m.run_fast(100), MimaState::Running);
+    /// assert_eq!(m.cycle_count, 100);
+    /// ```
+    pub fn run_fast(&mut self, max_cycles: u64) -> MimaState {
+        if self.history_depth > 0 {
+            for _ in 0..max_cycles {
+                let state = self.cycle(&NoLogging);
+                if state != MimaState::Running {
+                    return state;
+                }
+            }
+            return MimaState::Running;
+        }
+        self.history.clear();
+        for _ in 0..max_cycles {
+            let state = self.cycle_inner(&NoLogging);
+            if state != MimaState::Running {
+                return state;
+            }
+        }
         MimaState::Running
     }
 
+    /// Advance the MIMA by a whole instruction.
+    ///
+    /// Cycles are executed until the microprogram returns to the fetch phase or
+    /// the state changes from `MimaState::Running`. The state of the last cycle
+    /// is returned.
+    pub fn step_instruction<L: Logger>(&mut self, log: &L) -> MimaState {
+        loop {
+            let state = self.cycle(log);
+            if state != MimaState::Running || self.next_instruction == 0 {
+                return state;
+            }
+        }
+    }
+
+    /// Render the given memory word as assembly, using the loaded firmware.
+    ///
+    /// Instructions without an operand are shown without an argument. Words
+    /// with an unknown opcode are shown as data.
+    ///
+    /// ```rust
+    /// use mimar::Mima;
+    /// use mimar::firmware::{Instruction, InstructionKind, OperandKind};
+    /// let mut m = Mima::new();
+    /// for &(mnemonic, opcode) in &[("LDV", 0x1), ("HALT", 0xF0)] {
+    ///     m.firmware.insert_instruction(Instruction {
+    ///         opcode: opcode,
+    ///         mnemonic: mnemonic.into(),
+    ///         start: 0,
+    ///         kind: InstructionKind::from_mnemonic(mnemonic),
+    ///         operand: OperandKind::from_opcode(opcode),
+    ///     });
+    /// }
+    /// assert_eq!(m.disassemble(0x100010), "LDV 0x00010");
+    /// assert_eq!(m.disassemble(0xF00000), "HALT");
+    /// assert_eq!(m.disassemble(0x200010), "DS 0x200010");
+    /// ```
+    pub fn disassemble(&self, word: u32) -> String {
+        match self.firmware.find_instruction(opcode(word)) {
+            Some(instruction) => {
+                let mut param = word & masks::ADDRESS_MASK;
+                if instruction.opcode > 0xF {
+                    param &= !masks::EXTENDED;
+                }
+                match instruction.operand {
+                    OperandKind::None => instruction.mnemonic.clone(),
+                    _ => format!("{} {:#07x}", instruction.mnemonic, param),
+                }
+            },
+            None => format!("DS {:#08x}", word),
+        }
+    }
+
     /// Let the program continue at the given address.
     pub fn jump(&mut self, address: u32) {
         self.set_register(Register::IAR, address);
@@ -290,30 +1210,70 @@ impl Mima {
     /// Load memory and labels from the given reader.
     ///
     /// The memory and labels will be cleared before.
+    ///
+    /// Each line contains an address and a value, optionally followed by
+    /// labels for the address after a `;`. Lines starting with `//` and
+    /// everything after a `#` are ignored. The parts may be separated by any
+    /// whitespace. This is the output format of `mimar-asm`. Memory dumps of
+    /// other toolchains, like cbdevnet's `mimasm`, are not supported.
+    ///
+    /// ```rust
+    /// use mimar::{Mima, MimaLoadError};
+    /// let mut m = Mima::new();
+    /// match m.load_from_str("0x0 0x1\n0x1 0x2\ngarbage\n") {
+    ///     Err(MimaLoadError::InvalidLine(3)) => (),
+    ///     other => panic!("unexpected result: {:?}", other),
+    /// }
+    ///
+    /// m.load_from_str("# data\n// 0x0 0x5\n0x0 0x7 # seven\n").unwrap();
+    /// assert_eq!(m.get_memory(0x0), 7);
+    ///
+    /// m.load_from_str("0x00010\t0x000005\t;A  B\n").unwrap();
+    /// assert_eq!(m.get_memory(0x10), 5);
+    /// assert_eq!(m.labels["B"], 0x10);
+    /// ```
     pub fn load<B: BufRead>(&mut self, reader: B) -> Result<(), MimaLoadError> {
         self.memory.clear();
         self.labels.clear();
-        for line in reader.lines() {
+        self.highest_loaded = None;
+        for (line_no, line) in reader.lines().enumerate() {
             let line = try!(line);
+            // strip comments, skipping lines that contain nothing else
+            let line = line[..line.find('#').unwrap_or(line.len())].trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
             let mut splitted = line.split(";");
             let cell = splitted.next().unwrap();
             let comment = splitted.next();
-            let mut splitted = cell.split(" ");
-            let address = mtry!(splitted.next().map(str::trim).and_then(util::parse_num)) as u32;
-            let value = mtry!(splitted.next().map(str::trim).and_then(util::parse_num)) as u32;
+            let mut splitted = cell.split_whitespace();
+            let address = mtry!(splitted.next().and_then(util::parse_num), line_no) as u32;
+            let value = mtry!(splitted.next().and_then(util::parse_num), line_no) as u32;
             if value != 0 {
                 self.memory.insert(address, value);
             }
+            self.highest_loaded = Some(self.highest_loaded.map_or(address, |h| h.max(address)));
             if let Some(labels) = comment {
-                for label in labels.split(" ") {
-                    let label = label.trim();
-                    if label.is_empty() {
-                        continue;
-                    }
+                for label in labels.split_whitespace() {
                     self.labels.insert(label.into(), address);
                 }
             }
         }
         Ok(())
     }
+
+    /// Load memory and labels from the given string.
+    ///
+    /// See `load` for details.
+    ///
+    /// ```rust
+    /// use mimar::Mima;
+    /// let mut m = Mima::new();
+    /// m.load_from_str("0x00010 0x00002a ;ANSWER\n").unwrap();
+    /// assert_eq!(m.get_memory(0x10), 42);
+    /// assert_eq!(m.labels["ANSWER"], 0x10);
+    /// ```
+    pub fn load_from_str(&mut self, text: &str) -> Result<(), MimaLoadError> {
+        self.load(text.as_bytes())
+    }
 }
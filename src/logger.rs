@@ -4,8 +4,13 @@
 //! the `Logger` trait. This is useful if you want to control what (and how
 //! much) output is generated.
 
-use super::Mima;
-use super::firmware::Instruction;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+
+use super::{masks, Mima};
+use super::firmware::{AluOp, Instruction};
+use super::registers::Register;
 
 /// Trait for objects that can log MIMA actions.
 ///
@@ -13,9 +18,237 @@ use super::firmware::Instruction;
 #[allow(unused_variables)]
 pub trait Logger {
     fn log_instruction(&self, mima: &Mima, iar: u32, instr: &Instruction, param: u32) {}
+
+    /// Called before the microinstruction `word` at `micro_address` is executed.
+    fn log_microstep(&self, mima: &Mima, micro_address: u8, word: u32) {}
+
+    /// Called when the MIMA writes a byte to the output device.
+    fn log_output(&self, byte: u8) {}
+
+    /// Called when the MIMA does something suspicious, like modifying code.
+    fn log_warning(&self, mima: &Mima, message: &str) {}
+
+    /// Called after the ALU computed `result` from the inputs `x` and `y`.
+    ///
+    /// Use `Mima::last_add_overflowed` to check whether an addition overflowed.
+    fn log_alu(&self, mima: &Mima, op: AluOp, x: u32, y: u32, result: u32) {}
+}
+
+impl<'a, L: Logger + ?Sized> Logger for &'a L {
+    fn log_instruction(&self, mima: &Mima, iar: u32, instr: &Instruction, param: u32) {
+        (**self).log_instruction(mima, iar, instr, param)
+    }
+
+    fn log_microstep(&self, mima: &Mima, micro_address: u8, word: u32) {
+        (**self).log_microstep(mima, micro_address, word)
+    }
+
+    fn log_output(&self, byte: u8) {
+        (**self).log_output(byte)
+    }
+
+    fn log_warning(&self, mima: &Mima, message: &str) {
+        (**self).log_warning(mima, message)
+    }
+
+    fn log_alu(&self, mima: &Mima, op: AluOp, x: u32, y: u32, result: u32) {
+        (**self).log_alu(mima, op, x, y, result)
+    }
 }
 
 /// Object which does not generate any logging.
 pub struct NoLogging;
 
 impl Logger for NoLogging {}
+
+/// Logger which passes everything to two other loggers, first to `A`, then to
+/// `B`.
+///
+/// Loggers are also implemented for references, so the combined loggers can
+/// still be used after the run. More than two loggers can be combined by
+/// nesting.
+///
+/// ```rust
+/// use mimar::Mima;
+/// use mimar::logger::{CompositeLogger, CoverageLogger};
+/// use mimar::masks::*;
+/// let mut m = Mima::new();
+/// m.firmware.load_memory(&[ONE_WRITE | ACCU_READ | 0x01, 0x01]);
+/// let (first, second) = (CoverageLogger::new(), CoverageLogger::new());
+/// m.cycle(&CompositeLogger::new(&first, &second));
+/// assert_eq!(first.covered(), second.covered());
+/// assert!(first.covered().contains(&0x00));
+/// ```
+#[derive(Debug)]
+pub struct CompositeLogger<A: Logger, B: Logger> {
+    first: A,
+    second: B,
+}
+
+impl<A: Logger, B: Logger> CompositeLogger<A, B> {
+    /// Combine the two given loggers.
+    pub fn new(first: A, second: B) -> CompositeLogger<A, B> {
+        CompositeLogger {
+            first: first,
+            second: second,
+        }
+    }
+
+    /// Return the combined loggers.
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+impl<A: Logger, B: Logger> Logger for CompositeLogger<A, B> {
+    fn log_instruction(&self, mima: &Mima, iar: u32, instr: &Instruction, param: u32) {
+        self.first.log_instruction(mima, iar, instr, param);
+        self.second.log_instruction(mima, iar, instr, param);
+    }
+
+    fn log_microstep(&self, mima: &Mima, micro_address: u8, word: u32) {
+        self.first.log_microstep(mima, micro_address, word);
+        self.second.log_microstep(mima, micro_address, word);
+    }
+
+    fn log_output(&self, byte: u8) {
+        self.first.log_output(byte);
+        self.second.log_output(byte);
+    }
+
+    fn log_warning(&self, mima: &Mima, message: &str) {
+        self.first.log_warning(mima, message);
+        self.second.log_warning(mima, message);
+    }
+
+    fn log_alu(&self, mima: &Mima, op: AluOp, x: u32, y: u32, result: u32) {
+        self.first.log_alu(mima, op, x, y, result);
+        self.second.log_alu(mima, op, x, y, result);
+    }
+}
+
+/// Logger which records the executed microinstructions.
+///
+/// This is useful to check which parts of a firmware are exercised by a
+/// program.
+#[derive(Debug, Default)]
+pub struct CoverageLogger {
+    covered: RefCell<BTreeSet<u8>>,
+}
+
+impl CoverageLogger {
+    /// Create a new logger with no covered addresses.
+    pub fn new() -> CoverageLogger {
+        Default::default()
+    }
+
+    /// Return the microaddresses that have been executed.
+    pub fn covered(&self) -> BTreeSet<u8> {
+        self.covered.borrow().clone()
+    }
+}
+
+impl Logger for CoverageLogger {
+    fn log_microstep(&self, _: &Mima, micro_address: u8, _: u32) {
+        self.covered.borrow_mut().insert(micro_address);
+    }
+}
+
+/// Logger which writes a value change dump (VCD) of the MIMA.
+///
+/// The registers, the bus and the ALU control lines are sampled before each
+/// microinstruction, with the cycle count as time. The resulting file can be
+/// viewed with a waveform viewer like GTKWave.
+///
+/// ```rust
+/// use mimar::Mima;
+/// use mimar::logger::VcdLogger;
+/// use mimar::masks::*;
+/// let mut m = Mima::new();
+/// m.firmware.load_memory(&[ACCU_WRITE | X_READ | 0x01, ALU_C2 | ALU_C1]);
+/// let log = VcdLogger::new(Vec::new()).unwrap();
+/// m.cycle(&log);
+/// m.cycle(&log);
+/// let vcd = String::from_utf8(log.into_inner().unwrap()).unwrap();
+/// assert!(vcd.contains("$var wire 24 ! Accu $end"));
+/// assert!(vcd.contains("#2\nbz *\nb110 +\n"));
+/// ```
+#[derive(Debug)]
+pub struct VcdLogger<W: Write> {
+    out: RefCell<W>,
+    /// The last written value of each signal
+    last: RefCell<Vec<Option<String>>>,
+    /// The first error that happened while writing
+    error: RefCell<Option<io::Error>>,
+}
+
+impl<W: Write> VcdLogger<W> {
+    /// Create a new logger and write the VCD header to the given writer.
+    pub fn new(mut out: W) -> io::Result<VcdLogger<W>> {
+        try!(writeln!(out, "$timescale 1ns $end"));
+        try!(writeln!(out, "$scope module mima $end"));
+        let mut signals = 0;
+        for register in Register::all() {
+            try!(writeln!(out, "$var wire {} {} {} $end",
+                          register.width(), VcdLogger::<W>::identifier(signals), register));
+            signals += 1;
+        }
+        try!(writeln!(out, "$var wire 24 {} bus $end", VcdLogger::<W>::identifier(signals)));
+        try!(writeln!(out, "$var wire 3 {} alu $end", VcdLogger::<W>::identifier(signals + 1)));
+        try!(writeln!(out, "$upscope $end"));
+        try!(writeln!(out, "$enddefinitions $end"));
+        Ok(VcdLogger {
+            out: RefCell::new(out),
+            last: RefCell::new(vec![None; signals + 2]),
+            error: RefCell::new(None),
+        })
+    }
+
+    /// Return the writer, or the first error that happened while writing.
+    pub fn into_inner(self) -> io::Result<W> {
+        match self.error.into_inner() {
+            Some(error) => Err(error),
+            None => Ok(self.out.into_inner()),
+        }
+    }
+
+    /// Return the VCD identifier of the signal with the given index.
+    fn identifier(index: usize) -> char {
+        (b'!' + index as u8) as char
+    }
+
+    fn write_sample(&self, mima: &Mima, word: u32) -> io::Result<()> {
+        let mut values = Vec::new();
+        for register in Register::all() {
+            values.push(format!("b{:b}", mima.get_register(*register)));
+        }
+        let bus = Register::all().iter()
+            .find(|r| r.write_bit().map_or(false, |bit| word & bit > 0))
+            .map(|r| format!("b{:b}", mima.get_register(*r)))
+            .unwrap_or("bz".into());
+        values.push(bus);
+        values.push(format!("b{:b}", masks::alu_bits(word)));
+
+        let mut out = self.out.borrow_mut();
+        let mut last = self.last.borrow_mut();
+        try!(writeln!(out, "#{}", mima.cycle_count));
+        for (i, value) in values.into_iter().enumerate() {
+            if last[i].as_ref() != Some(&value) {
+                try!(writeln!(out, "{} {}", value, VcdLogger::<W>::identifier(i)));
+                last[i] = Some(value);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Logger for VcdLogger<W> {
+    fn log_microstep(&self, mima: &Mima, _: u8, word: u32) {
+        if let Err(error) = self.write_sample(mima, word) {
+            let mut stored = self.error.borrow_mut();
+            if stored.is_none() {
+                *stored = Some(error);
+            }
+        }
+    }
+}
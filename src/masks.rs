@@ -1,44 +1,67 @@
 //! Various bitmasks used in the binary format of MIMA commands and MIMA
 //! microcommands.
 
-macro_rules! bits {
-    ($start:expr, ) => {};
+// Defines a constant for every control line and the `CONTROL_LINES` table.
+macro_rules! control_lines {
+    ($($name:ident = $bit:expr,)*) => {
+        $(pub const $name: u32 = 1 << $bit;)*
 
-    ($start:expr, $name:ident, $($names:ident,)*) => {
-        pub const $name: u32 = 1 << $start;
-        bits!($start - 1, $($names,)*);
+        /// All control lines of a microinstruction with their name and mask.
+        ///
+        /// This table is the single place that defines the layout of a
+        /// microinstruction; the simulator, the decoder and the compiler only
+        /// use the constants generated from it. To use the free bits (8 and 9
+        /// in the stock layout) for additional control lines, add them here.
+        /// The ALU control lines have to stay adjacent, with `ALU_C0` as the
+        /// lowest one, and no line may use the low 8 bits, which hold the
+        /// address of the next microinstruction.
+        ///
+        /// ```rust
+        /// use mimar::masks::*;
+        /// let mut used = 0;
+        /// for &(_, mask) in CONTROL_LINES {
+        ///     assert_eq!(used & mask, 0);
+        ///     used |= mask;
+        /// }
+        /// assert_eq!(used & !MICRO_DATA, 0);
+        /// assert_eq!(ALU_CONTROL >> ALU_SHIFT, 0b111);
+        /// ```
+        pub const CONTROL_LINES: &'static [(&'static str, u32)] = &[
+            $((stringify!($name), $name),)*
+        ];
     }
 }
 
-bits! {
-    27,
-    ACCU_READ,
-    ACCU_WRITE,
-    X_READ,
-    Y_READ,
-    Z_WRITE,
-    ONE_WRITE,
-    IAR_READ,
-    IAR_WRITE,
-    IR_READ,
-    IR_WRITE,
-    SDR_READ,
-    SDR_WRITE,
-    SAR_READ,
-    ALU_C2,
-    ALU_C1,
-    ALU_C0,
-    MEM_READ,
-    MEM_WRITE,
+control_lines! {
+    ACCU_READ = 27,
+    ACCU_WRITE = 26,
+    X_READ = 25,
+    Y_READ = 24,
+    Z_WRITE = 23,
+    ONE_WRITE = 22,
+    IAR_READ = 21,
+    IAR_WRITE = 20,
+    IR_READ = 19,
+    IR_WRITE = 18,
+    SDR_READ = 17,
+    SDR_WRITE = 16,
+    SAR_READ = 15,
+    ALU_C2 = 14,
+    ALU_C1 = 13,
+    ALU_C0 = 12,
+    MEM_READ = 11,
+    MEM_WRITE = 10,
 }
 
 pub const MEM_ACCESS: u32 = MEM_READ | MEM_WRITE;
 
 pub const ALU_CONTROL: u32 = ALU_C2 | ALU_C1 | ALU_C0;
-pub const ALU_SHIFT: u32 = 12;
+pub const ALU_SHIFT: u32 = ALU_C0.trailing_zeros();
 
+/// Number of bits of a microinstruction, at most 32.
+pub const MICRO_WIDTH: u32 = 28;
 pub const MICRO_NEXT: u32 = 0xFF;
-pub const MICRO_DATA: u32 = 0xFFFFF00;
+pub const MICRO_DATA: u32 = (!0 >> (32 - MICRO_WIDTH)) & !MICRO_NEXT;
 
 pub const OPCODE_SHIFT: u32 = 20;
 pub const OPCODE: u32 = 0xF << OPCODE_SHIFT;
@@ -47,3 +70,36 @@ pub const EXTENDED: u32 = 0xFF << EXTENDED_SHIFT;
 
 pub const DATA_MASK: u32 = 0xFFFFFF;
 pub const ADDRESS_MASK: u32 = 0xFFFFF;
+
+/// Return the address of the next microinstruction.
+///
+/// ```rust
+/// use mimar::masks::*;
+/// let word = ACCU_WRITE | ALU_C0 | MEM_READ | 0x42;
+/// assert_eq!(micro_next(word), 0x42);
+/// assert_eq!(alu_bits(word), 0b001);
+/// assert_eq!(mem_access(word), MEM_READ);
+/// assert_eq!(set_next(word, 0xFF), ACCU_WRITE | ALU_C0 | MEM_READ | 0xFF);
+/// ```
+#[inline]
+pub fn micro_next(word: u32) -> u8 {
+    (word & MICRO_NEXT) as u8
+}
+
+/// Return the ALU control bits (`C2 C1 C0`), shifted to the lowest bits.
+#[inline]
+pub fn alu_bits(word: u32) -> u32 {
+    (word & ALU_CONTROL) >> ALU_SHIFT
+}
+
+/// Return the memory access bits (`MEM_READ` and `MEM_WRITE`).
+#[inline]
+pub fn mem_access(word: u32) -> u32 {
+    word & MEM_ACCESS
+}
+
+/// Replace the address of the next microinstruction.
+#[inline]
+pub fn set_next(word: u32, target: u8) -> u32 {
+    (word & MICRO_DATA) | target as u32
+}
@@ -1,11 +1,28 @@
 //! Information about the various registers in the MIMA.
 
+use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
 
-use super::masks;
+use super::{masks, util};
 
 /// Enum containing all available registers.
+///
+/// Registers can be parsed from their name, ignoring the case. The German
+/// names from the KIT course are accepted as well:
+///
+/// ```rust
+/// use mimar::registers::Register;
+/// assert_eq!("IAR".parse::<Register>().ok(), Some(Register::IAR));
+/// assert_eq!("Befehlsadressregister".parse::<Register>().ok(), Some(Register::IAR));
+/// assert_eq!("akku".parse::<Register>().ok(), Some(Register::Accu));
+/// assert_eq!("SPEICHERDATENREGISTER".parse::<Register>().ok(), Some(Register::SDR));
+/// assert!("foo".parse::<Register>().is_err());
+/// ```
+///
+/// Custom registers are parsed from `Custom(n)`, the form they are displayed
+/// in.
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Register {
     /// Accumulator
     Accu,
@@ -25,46 +42,106 @@ pub enum Register {
     SAR,
     /// storage data register
     SDR,
+    /// Additional register, described by the `RegisterDescription` with the
+    /// given index in `Mima::custom_registers`.
+    Custom(u8),
+}
+
+/// Description of an additional register.
+///
+/// Additional registers are added with `Mima::add_register`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RegisterDescription {
+    /// The name of the register.
+    pub name: String,
+    /// The number of bits the register can hold.
+    pub width: u8,
+    /// The bit that lets the register read from the bus.
+    pub read_bit: Option<u32>,
+    /// The bit that lets the register write to the bus.
+    pub write_bit: Option<u32>,
 }
 
 impl Register {
-    /// Return an array of all registers.
+    /// Return an array of all stock registers.
     pub fn all() -> &'static [Register] {
         use self::Register::*;
         static REGISTERS: [Register; 9] = [Accu, One, IAR, IR, X, Y, Z, SAR, SDR];
         &REGISTERS
     }
 
-    /// Returns the bits that are responsible for controlling this register.
+    /// Return the bit that lets this register read from the bus.
     ///
-    /// The return format is `(read_bit, write_bit)`. If one of those is not
-    /// available (e.g. for a read-only register), `None` is returned at this
-    /// position.
-    pub fn control_bits(&self) -> (Option<u32>, Option<u32>) {
+    /// `None` is returned if the register can't be set from the bus (like
+    /// `One`). Custom registers have no fixed control bits, use
+    /// `Mima::register_read_bit` for them.
+    ///
+    /// ```rust
+    /// use mimar::masks;
+    /// use mimar::registers::Register;
+    /// assert_eq!(Register::X.read_bit(), Some(masks::X_READ));
+    /// assert_eq!(Register::Z.read_bit(), None);
+    /// ```
+    pub fn read_bit(&self) -> Option<u32> {
+        match *self {
+            Register::Accu => Some(masks::ACCU_READ),
+            Register::IAR => Some(masks::IAR_READ),
+            Register::IR => Some(masks::IR_READ),
+            Register::X => Some(masks::X_READ),
+            Register::Y => Some(masks::Y_READ),
+            Register::SAR => Some(masks::SAR_READ),
+            Register::SDR => Some(masks::SDR_READ),
+            Register::One | Register::Z | Register::Custom(_) => None,
+        }
+    }
+
+    /// Return the bit that lets this register write to the bus.
+    ///
+    /// `None` is returned if the register can't drive the bus (like `X`).
+    /// Custom registers have no fixed control bits, use
+    /// `Mima::register_write_bit` for them.
+    ///
+    /// ```rust
+    /// use mimar::masks;
+    /// use mimar::registers::Register;
+    /// assert_eq!(Register::Z.write_bit(), Some(masks::Z_WRITE));
+    /// assert_eq!(Register::X.write_bit(), None);
+    /// ```
+    pub fn write_bit(&self) -> Option<u32> {
         match *self {
-            Register::Accu => (Some(masks::ACCU_READ), Some(masks::ACCU_WRITE)),
-            Register::One => (None, Some(masks::ONE_WRITE)),
-            Register::IAR => (Some(masks::IAR_READ), Some(masks::IAR_WRITE)),
-            Register::IR => (Some(masks::IR_READ), Some(masks::IR_WRITE)),
-            Register::X => (Some(masks::X_READ), None),
-            Register::Y => (Some(masks::Y_READ), None),
-            Register::Z => (None, Some(masks::Z_WRITE)),
-            Register::SAR => (Some(masks::SAR_READ), None),
-            Register::SDR => (Some(masks::SDR_READ), Some(masks::SDR_WRITE)),
+            Register::Accu => Some(masks::ACCU_WRITE),
+            Register::One => Some(masks::ONE_WRITE),
+            Register::IAR => Some(masks::IAR_WRITE),
+            Register::IR => Some(masks::IR_WRITE),
+            Register::Z => Some(masks::Z_WRITE),
+            Register::SDR => Some(masks::SDR_WRITE),
+            Register::X | Register::Y | Register::SAR | Register::Custom(_) => None,
         }
     }
 
+    /// Returns the bits that are responsible for controlling this register.
+    ///
+    /// The return format is `(read_bit, write_bit)`, see `read_bit` and
+    /// `write_bit`.
+    pub fn control_bits(&self) -> (Option<u32>, Option<u32>) {
+        (self.read_bit(), self.write_bit())
+    }
+
     /// Return true if the register is readable.
     pub fn is_readable(&self) -> bool {
-        self.control_bits().0.is_some()
+        self.read_bit().is_some()
     }
 
     /// Return true if the register is writeable.
     pub fn is_writeable(&self) -> bool {
-        self.control_bits().1.is_some()
+        self.write_bit().is_some()
     }
 
     /// Return the register width, i.e. the number of bits it can hold.
+    ///
+    /// Custom registers are assumed to be 24 bits wide, use
+    /// `Mima::register_width` to get their actual width.
     pub fn width(&self) -> u8 {
         match *self {
             Register::Accu => 24,
@@ -76,12 +153,40 @@ impl Register {
             Register::Z => 24,
             Register::SAR => 20,
             Register::SDR => 24,
+            Register::Custom(_) => 24,
         }
     }
 
     /// Return the bitmask for values of this register.
     pub fn value_bits(&self) -> u32 {
-        2u32.pow(self.width() as u32) - 1
+        util::mask(self.width() as u32)
+    }
+}
+
+impl Display for Register {
+    /// Format the canonical name of the register, which can be parsed again.
+    ///
+    /// ```rust
+    /// use mimar::registers::Register;
+    /// for register in Register::all() {
+    ///     assert_eq!(register.to_string().parse::<Register>().ok(), Some(*register));
+    /// }
+    /// assert_eq!(Register::Custom(3).to_string(), "Custom(3)");
+    /// assert_eq!("custom(3)".parse::<Register>().ok(), Some(Register::Custom(3)));
+    /// ```
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match *self {
+            Register::Accu => write!(fmt, "Accu"),
+            Register::One => write!(fmt, "One"),
+            Register::IAR => write!(fmt, "IAR"),
+            Register::IR => write!(fmt, "IR"),
+            Register::X => write!(fmt, "X"),
+            Register::Y => write!(fmt, "Y"),
+            Register::Z => write!(fmt, "Z"),
+            Register::SAR => write!(fmt, "SAR"),
+            Register::SDR => write!(fmt, "SDR"),
+            Register::Custom(i) => write!(fmt, "Custom({})", i),
+        }
     }
 }
 
@@ -96,13 +201,18 @@ impl FromStr for Register {
         match &lower as &str {
             "accu" | "akku" => Ok(Register::Accu),
             "one" | "eins" => Ok(Register::One),
-            "iar" => Ok(Register::IAR),
-            "ir" => Ok(Register::IR),
+            "iar" | "befehlsadressregister" => Ok(Register::IAR),
+            "ir" | "befehlsregister" => Ok(Register::IR),
             "x" => Ok(Register::X),
             "y" => Ok(Register::Y),
             "z" => Ok(Register::Z),
-            "sar" => Ok(Register::SAR),
-            "sdr" => Ok(Register::SDR),
+            "sar" | "speicheradressregister" => Ok(Register::SAR),
+            "sdr" | "speicherdatenregister" => Ok(Register::SDR),
+            _ if lower.starts_with("custom(") && lower.ends_with(")") => {
+                lower["custom(".len()..lower.len() - 1].parse()
+                    .map(Register::Custom)
+                    .map_err(|_| UnknownRegister)
+            },
             _ => Err(UnknownRegister),
         }
     }
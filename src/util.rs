@@ -1,9 +1,17 @@
 //! Utility functions that are useful in many cases.
 
+use std::cmp;
+
 /// Parse a number.
 ///
 /// The number is assumed to be decimal. If a 0x or $ prefix is found, the
-/// number is parsed as hexadecimal instead.
+/// number is parsed as hexadecimal instead. A 0b prefix denotes a binary
+/// number, a 0o prefix an octal number.
+///
+/// Underscores may be used to separate digits, but only between two digits.
+///
+/// If the number does not fit into an `i32`, or if there are no digits at all
+/// (like in `""`, `"-"` or `"0x"`), `None` is returned.
 ///
 /// ```rust
 /// use mimar::util::parse_num;
@@ -11,10 +19,28 @@
 /// assert_eq!(parse_num("0x10"), Some(16));
 /// assert_eq!(parse_num("$10"), Some(16));
 /// assert_eq!(parse_num("-0xF"), Some(-15));
+/// assert_eq!(parse_num("0b1010"), Some(10));
+/// assert_eq!(parse_num("-0b11"), Some(-3));
+/// assert_eq!(parse_num("0b12"), None);
+/// assert_eq!(parse_num("0o17"), Some(15));
+/// assert_eq!(parse_num("0o8"), None);
+/// assert_eq!(parse_num("0x00_FF"), Some(255));
+/// assert_eq!(parse_num("1_000_000"), Some(1000000));
+/// assert_eq!(parse_num("_1"), None);
+/// assert_eq!(parse_num("1_"), None);
+/// assert_eq!(parse_num("1__0"), None);
+/// assert_eq!(parse_num("0xFFFFFFFFF"), None);
+/// assert_eq!(parse_num("-2147483648"), Some(-2147483648));
 /// assert_eq!(parse_num("foo"), None);
+/// assert_eq!(parse_num("0"), Some(0));
+/// assert_eq!(parse_num(""), None);
+/// assert_eq!(parse_num("0x"), None);
+/// assert_eq!(parse_num("$"), None);
+/// assert_eq!(parse_num("-"), None);
+/// assert_eq!(parse_num("-0b"), None);
 /// ```
 pub fn parse_num(text: &str) -> Option<i32> {
-    let mut result = 0;
+    let mut result: i32 = 0;
     let mut base = 10;
     let mut stripped = text;
     let sign = if stripped.starts_with("-") {
@@ -29,16 +55,82 @@ pub fn parse_num(text: &str) -> Option<i32> {
     } else if stripped.starts_with("$") {
         stripped = &stripped[1..];
         base = 16;
+    } else if stripped.starts_with("0b") {
+        stripped = &stripped[2..];
+        base = 2;
+    } else if stripped.starts_with("0o") {
+        stripped = &stripped[2..];
+        base = 8;
     };
+    if stripped.is_empty() || stripped.ends_with("_") {
+        return None;
+    }
+    let mut last = None;
     for chr in stripped.chars() {
-        result *= base;
-        if let Some(d) = chr.to_digit(base as u32) {
-            result += d as i32
-        } else {
-            return None;
+        if chr == '_' {
+            // Separators are only allowed after a digit
+            if last.map_or(true, |c| c == '_') {
+                return None;
+            }
+            last = Some(chr);
+            continue;
         }
+        last = Some(chr);
+        let digit = match chr.to_digit(base as u32) {
+            Some(d) => d as i32,
+            None => return None,
+        };
+        // Accumulate with the sign applied, so that i32::MIN is still valid
+        result = match result.checked_mul(base).and_then(|r| r.checked_add(sign * digit)) {
+            Some(r) => r,
+            None => return None,
+        };
+    }
+    Some(result)
+}
+
+/// Parse an assignment of the form `target=value`.
+///
+/// The target is returned as-is, so it can be either an address or a label. The
+/// value is parsed with `parse_num`.
+///
+/// ```rust
+/// use mimar::util::parse_assignment;
+/// assert_eq!(parse_assignment("NUMBER=10"), Some(("NUMBER".into(), 10)));
+/// assert_eq!(parse_assignment("0x10=-1"), Some(("0x10".into(), -1)));
+/// assert_eq!(parse_assignment("NUMBER"), None);
+/// assert_eq!(parse_assignment("=10"), None);
+/// assert_eq!(parse_assignment("NUMBER=foo"), None);
+/// assert_eq!(parse_assignment("A=1=2"), None);
+/// ```
+pub fn parse_assignment(text: &str) -> Option<(String, i32)> {
+    split_assignment(text)
+        .and_then(|(target, value)| parse_num(value).map(|value| (target.into(), value)))
+}
+
+/// Split an assignment of the form `target=value` into target and value.
+///
+/// Unlike `parse_assignment`, the value is not parsed, so it can be a label
+/// as well.
+///
+/// ```rust
+/// use mimar::util::split_assignment;
+/// assert_eq!(split_assignment("PTR=TARGET"), Some(("PTR", "TARGET")));
+/// assert_eq!(split_assignment(" 0x10 = 5 "), Some(("0x10", "5")));
+/// assert_eq!(split_assignment("PTR="), None);
+/// assert_eq!(split_assignment("PTR"), None);
+/// ```
+pub fn split_assignment(text: &str) -> Option<(&str, &str)> {
+    let mut split = text.splitn(2, '=');
+    let target = split.next().unwrap().trim();
+    let value = match split.next() {
+        Some(value) => value.trim(),
+        None => return None,
+    };
+    if target.is_empty() || value.is_empty() {
+        return None;
     }
-    Some(sign * result)
+    Some((target, value))
 }
 
 /// Bit-rotate num to the right.
@@ -52,6 +144,130 @@ pub fn parse_num(text: &str) -> Option<i32> {
 /// assert_eq!(rar(1, 2), 2);
 /// ```
 pub fn rar(num: u32, width: u32) -> u32 {
-    let last = num & 1;
-    (num >> 1) | (last << (width - 1))
+    rar_n(num, width, 1)
+}
+
+/// Bit-rotate num to the right by count positions.
+///
+/// This works like `rar`, but rotates multiple times. A count greater than the
+/// width wraps around. A width of 0 always gives 0, widths above 32 are
+/// treated as 32.
+///
+/// ```rust
+/// use mimar::util::rar_n;
+/// assert_eq!(rar_n(1, 0, 1), 0);
+/// assert_eq!(rar_n(1, 40, 1), 0x80000000);
+/// assert_eq!(rar_n(1, 4, 2), 4);
+/// assert_eq!(rar_n(6, 4, 1), 3);
+/// assert_eq!(rar_n(1, 4, 4), 1);
+/// assert_eq!(rar_n(1, 4, 6), 4);
+/// ```
+pub fn rar_n(num: u32, width: u32, count: u32) -> u32 {
+    if width == 0 {
+        return 0;
+    }
+    let width = cmp::min(width, 32);
+    let count = count % width;
+    if count == 0 {
+        return num & mask(width);
+    }
+    let num = num & mask(width);
+    (num >> count) | ((num << (width - count)) & mask(width))
+}
+
+/// Interpret value as a two's-complement number with the given width.
+///
+/// The bits left of the width'th bit are ignored, so a width of 0 always
+/// gives 0. Widths above 32 are treated as 32.
+///
+/// ```rust
+/// use mimar::util::to_signed;
+/// assert_eq!(to_signed(0xFFFFFF, 0), 0);
+/// assert_eq!(to_signed(0xFFFFFFFF, 40), -1);
+/// assert_eq!(to_signed(0xFFFFFF, 24), -1);
+/// assert_eq!(to_signed(0x800000, 24), -8388608);
+/// assert_eq!(to_signed(0x7FFFFF, 24), 8388607);
+/// assert_eq!(to_signed(0x1000005, 24), 5);
+/// ```
+pub fn to_signed(value: u32, width: u8) -> i32 {
+    if width == 0 {
+        return 0;
+    }
+    let width = cmp::min(width, 32);
+    let value = value & mask(width as u32);
+    let sign_bit = 1 << (width - 1);
+    if value & sign_bit > 0 {
+        (value | !mask(width as u32)) as i32
+    } else {
+        value as i32
+    }
+}
+
+/// Return true if the sign bit of the two's-complement number with the given
+/// width is set.
+///
+/// A number with width 0 has no sign bit and is never negative. Widths above
+/// 32 are treated as 32.
+///
+/// ```rust
+/// use mimar::util::is_negative;
+/// assert!(!is_negative(0xFFFFFF, 0));
+/// assert!(is_negative(0x80000000, 40));
+/// assert!(is_negative(0x800000, 24));
+/// assert!(is_negative(0xFFFFFF, 24));
+/// assert!(!is_negative(0x7FFFFF, 24));
+/// assert!(!is_negative(0x0, 24));
+/// assert!(is_negative(0x8, 4));
+/// ```
+pub fn is_negative(value: u32, width: u8) -> bool {
+    width > 0 && value & (1 << (cmp::min(width, 32) - 1)) > 0
+}
+
+/// Convert value to a two's-complement number with the given width.
+///
+/// The result is truncated to width bits.
+///
+/// ```rust
+/// use mimar::util::from_signed;
+/// assert_eq!(from_signed(-1, 24), 0xFFFFFF);
+/// assert_eq!(from_signed(5, 24), 5);
+/// assert_eq!(from_signed(-2, 4), 0xE);
+/// ```
+pub fn from_signed(value: i32, width: u8) -> u32 {
+    value as u32 & mask(width as u32)
+}
+
+/// Format a value as hex, followed by its signed decimal value in brackets.
+///
+/// The value is interpreted as two's-complement number with the given width,
+/// see `to_signed`.
+///
+/// ```rust
+/// use mimar::util::format_signed;
+/// assert_eq!(format_signed(0xFFFFFF, 24), "0xffffff (-1)");
+/// assert_eq!(format_signed(0x7FFFFF, 24), "0x7fffff (8388607)");
+/// assert_eq!(format_signed(0x800000, 24), "0x800000 (-8388608)");
+/// assert_eq!(format_signed(5, 24), "     0x5 (5)");
+/// ```
+pub fn format_signed(value: u32, width: u8) -> String {
+    format!("{:#8x} ({})", value, to_signed(value, width))
+}
+
+/// Return a bitmask with the lowest width bits set.
+///
+/// Widths of 32 and more give a mask with all bits set.
+///
+/// ```rust
+/// use mimar::util::mask;
+/// assert_eq!(mask(0), 0);
+/// assert_eq!(mask(24), 0xFFFFFF);
+/// assert_eq!(mask(32), 0xFFFFFFFF);
+/// assert_eq!(mask(40), 0xFFFFFFFF);
+/// ```
+pub fn mask(width: u32) -> u32 {
+    if width >= 32 {
+        !0
+    } else {
+        (1 << width) - 1
+    }
 }
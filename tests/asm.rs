@@ -0,0 +1,112 @@
+//! Tests for the assembler.
+extern crate mimar;
+
+use mimar::Mima;
+use mimar::asm::{self, AsmError, Radix};
+use mimar::firmware::stock_firmware;
+
+fn assemble(source: &str) -> Result<asm::Assembly, AsmError> {
+    asm::assemble_full(&stock_firmware(), source.as_bytes(), Radix::Hex)
+}
+
+#[test]
+fn output_can_be_loaded() {
+    let source = "A: DS 5\nB: DS 7\nC: DS 0\n*= 0x100\nSTART: LDV A\nADD B\nSTV C\nHALT\n";
+    let output = asm::assemble(&stock_firmware(), source.as_bytes()).unwrap();
+    let mut m = Mima::load_program(include_str!("../src/default.mimafw").as_bytes(),
+                                   output.as_bytes(),
+                                   Some("START")).unwrap();
+    m.run_fast(1000);
+    assert_eq!(m.get_memory(m.labels["C"]), 12);
+}
+
+#[test]
+fn memory_and_labels() {
+    let (memory, labels) = asm::assemble_to_memory(&stock_firmware(),
+                                                   "*= 0x10\nLOOP: JMP LOOP+1\n".as_bytes())
+        .unwrap();
+    assert_eq!(memory.len(), 1);
+    assert_eq!(memory[&0x10], 0x800011);
+    assert_eq!(labels["LOOP"], 0x10);
+}
+
+#[test]
+fn align() {
+    let asm = assemble("*= 0x100\nLDC 1\nLDC 2\nLDC 3\nTABLE: ALIGN 16\nDS 7\n").unwrap();
+    assert_eq!(asm.labels["TABLE"], 0x110);
+    assert_eq!(asm.memory[&0x110], 7);
+
+    let asm = assemble("*= 0x110\nALIGN 16\nDS 1\n").unwrap();
+    assert_eq!(asm.memory[&0x110], 1);
+
+    match assemble("LDC 1\nALIGN 3\n") {
+        Err(AsmError::InvalidAlignment(2, _)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn overlapping_cells() {
+    match assemble("DS 1\n*= 0\nDS 2\n") {
+        Err(AsmError::AddressOverlap(3, 0)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    match assemble("LDC 1\nLDC 2\n*= 0\nLDC 3\n") {
+        Err(AsmError::AddressOverlap(4, 0)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn errors_carry_the_line_number() {
+    match assemble("A = 1\nB = 2\nA = 3\n") {
+        Err(AsmError::Redefinition(3, ref name)) => assert_eq!(name, "A"),
+        other => panic!("unexpected result {:?}", other),
+    }
+    match assemble("LDC 1\nDS FOO\n") {
+        Err(AsmError::UndefinedConstant(2, ref name)) => assert_eq!(name, "FOO"),
+        other => panic!("unexpected result {:?}", other),
+    }
+    match assemble("; comment\nLDC 1 2\n") {
+        Err(AsmError::InvalidLine(2, _)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn decimal_output() {
+    let asm = asm::assemble_full(&stock_firmware(), "*= 16\nX: DS 5\n".as_bytes(), Radix::Dec)
+        .unwrap();
+    assert_eq!(asm.output, "0000016 00000005 ;X\n");
+}
+
+#[test]
+fn resolutions() {
+    let asm = assemble("X: DS 5\nLDV X+1\n").unwrap();
+    assert_eq!(asm.resolutions.len(), 1);
+    assert_eq!(asm.resolutions[0].address, 0x1);
+    assert_eq!(asm.resolutions[0].label, "X");
+    assert_eq!(asm.resolutions[0].offset, 1);
+    assert_eq!(asm.resolutions[0].value, 0x1);
+}
+
+#[test]
+fn location_out_of_range() {
+    for source in &["*= 0x7FFFFFFF\n*= +0x7FFFFFFF\n",
+                    "RESW 0x7FFFFFFF\nRESW 0x7FFFFFFF\n",
+                    "*= 0xFFFFF\nDS 1, 2\n",
+                    "*= 0x100000\nHALT\n",
+                    "*= 0xFFFF0\nALIGN 0x40000000\n"] {
+        match assemble(source) {
+            Err(AsmError::LocationOutOfRange(..)) => (),
+            other => panic!("unexpected result {:?} for {:?}", other, source),
+        }
+    }
+    match assemble("*= 5\n*= -6\n") {
+        Err(AsmError::NegativeLocation(2, _)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    // the location may end right after the last address
+    let asm = assemble("*= 0xFFFFF\nDS 1\nEND:\n").unwrap();
+    assert_eq!(asm.labels["END"], 0x100000);
+}
@@ -0,0 +1,66 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+
+use mimar::Mima;
+use mimar::firmware::{Firmware, Instruction, InstructionKind, OperandKind};
+use mimar::logger::Logger;
+use mimar::masks::*;
+
+/// Logger which records the decoded instructions.
+#[derive(Default)]
+pub struct InstructionLogger {
+    pub instructions: RefCell<Vec<Instruction>>,
+}
+
+impl Logger for InstructionLogger {
+    fn log_instruction(&self, _: &Mima, _: u32, instr: &Instruction, _: u32) {
+        self.instructions.borrow_mut().push(instr.clone());
+    }
+}
+
+/// Build a firmware with the fetch phase and the LDV, STV and HALT commands.
+pub fn firmware() -> Firmware {
+    let mut firmware = Firmware::new();
+    firmware.load_memory(&[
+        // fetch
+        IAR_WRITE | SAR_READ | X_READ | MEM_READ | 0x01,
+        ONE_WRITE | Y_READ | MEM_READ | 0x02,
+        ALU_C0 | MEM_READ | 0x03,
+        Z_WRITE | IAR_READ | 0x04,
+        SDR_WRITE | IR_READ | 0xFF,
+        // LDV
+        IR_WRITE | SAR_READ | MEM_READ | 0x06,
+        MEM_READ | 0x07,
+        MEM_READ | 0x08,
+        SDR_WRITE | ACCU_READ,
+        // STV
+        ACCU_WRITE | SDR_READ | 0x0A,
+        IR_WRITE | SAR_READ | MEM_WRITE | 0x0B,
+        MEM_WRITE | 0x0C,
+        MEM_WRITE,
+    ]);
+    for &(mnemonic, opcode, start) in &[("LDV", 0x1, 0x05), ("STV", 0x2, 0x09), ("HALT", 0xF0, 0x0D)] {
+        firmware.insert_instruction(Instruction {
+            opcode: opcode,
+            mnemonic: mnemonic.into(),
+            start: start,
+            kind: InstructionKind::from_mnemonic(mnemonic),
+            operand: OperandKind::from_opcode(opcode),
+        });
+    }
+    firmware
+}
+
+/// Build a MIMA that copies the cell 0x10 to 0x11 and halts.
+pub fn mima() -> Mima {
+    Mima::builder()
+        .firmware(firmware())
+        .set_cell(0x0, 0x100010)
+        .set_cell(0x1, 0x200011)
+        .set_cell(0x2, 0x100011)
+        .set_cell(0x3, 0xF00000)
+        .set_cell(0x10, 42)
+        .build()
+}
@@ -0,0 +1,20 @@
+//! Tests for the microinstruction coverage recording.
+extern crate mimar;
+
+mod common;
+
+use mimar::MimaState;
+use mimar::logger::CoverageLogger;
+
+#[test]
+fn covers_executed_microinstructions() {
+    let mut m = common::mima();
+    let log = CoverageLogger::new();
+    while m.cycle(&log) == MimaState::Running {}
+
+    let covered = log.covered();
+    // fetch phase, LDV and STV
+    assert_eq!(covered, (0x00..0x0D).collect());
+    // HALT has no microprogram
+    assert!(!covered.contains(&0x0D));
+}
@@ -0,0 +1,56 @@
+//! Tests for the memory-mapped devices.
+extern crate mimar;
+
+mod common;
+
+use std::cell::RefCell;
+
+use mimar::MimaState;
+use mimar::logger::{Logger, NoLogging};
+
+#[derive(Default)]
+struct OutputLogger {
+    output: RefCell<Vec<u8>>,
+}
+
+impl Logger for OutputLogger {
+    fn log_output(&self, byte: u8) {
+        self.output.borrow_mut().push(byte);
+    }
+}
+
+#[test]
+fn output_device() {
+    let mut m = common::mima();
+    m.set_output_address(0x11);
+    let log = OutputLogger::default();
+    while m.cycle(&log) == MimaState::Running {}
+    assert_eq!(*log.output.borrow(), b"*");
+    assert_eq!(m.get_memory(0x11), 42);
+}
+
+#[test]
+fn no_output_by_default() {
+    let mut m = common::mima();
+    let log = OutputLogger::default();
+    while m.cycle(&log) == MimaState::Running {}
+    assert!(log.output.borrow().is_empty());
+}
+
+#[test]
+fn input_device() {
+    let mut m = common::mima();
+    m.set_input_address(0x10);
+    m.push_input(7);
+    while m.cycle(&NoLogging) == MimaState::Running {}
+    assert_eq!(m.get_memory(0x11), 7);
+    assert!(m.input.is_empty());
+}
+
+#[test]
+fn empty_input_reads_zero() {
+    let mut m = common::mima();
+    m.set_input_address(0x10);
+    while m.cycle(&NoLogging) == MimaState::Running {}
+    assert_eq!(m.get_memory(0x11), 0);
+}
@@ -0,0 +1,58 @@
+//! End-to-end tests for instructions with extended opcodes, using the default
+//! firmware.
+extern crate mimar;
+
+mod common;
+
+use mimar::{Mima, MimaState};
+use mimar::firmware::{stock_firmware, Instruction};
+
+use common::InstructionLogger;
+
+/// Run the given program (in the output format of `mimar-asm`) until it stops.
+fn run(program: &str) -> (Mima, Vec<Instruction>) {
+    let mut m = Mima::builder()
+        .firmware(stock_firmware())
+        .memory_from_str(program)
+        .unwrap()
+        .build();
+    let log = InstructionLogger::default();
+    let mut state = MimaState::Running;
+    while state == MimaState::Running {
+        state = m.cycle(&log);
+    }
+    assert_eq!(state, MimaState::Halted);
+    let instructions = log.instructions.into_inner();
+    (m, instructions)
+}
+
+#[test]
+fn not() {
+    // LDC 5
+    // NOT
+    // HALT
+    let (m, instructions) = run("0x0 0x000005\n0x1 0xF10000\n0x2 0xF00000\n");
+    assert_eq!(m.accu(), 0xFFFFFA);
+    let opcodes = instructions.iter().map(|i| i.opcode).collect::<Vec<_>>();
+    assert_eq!(opcodes, vec![0x0, 0xF1, 0xF0]);
+}
+
+#[test]
+fn rar() {
+    // LDC 3
+    // RAR
+    // RAR
+    // HALT
+    let (m, instructions) = run("0x0 0x000003\n0x1 0xF20000\n0x2 0xF20000\n0x3 0xF00000\n");
+    assert_eq!(m.accu(), 0xC00000);
+    assert_eq!(instructions[1].mnemonic, "RAR");
+    assert_eq!(instructions[1], *m.firmware.find_instruction(0xF2).unwrap());
+}
+
+#[test]
+fn extended_argument_is_ignored() {
+    // NOT with garbage in the address bits, which must not change the opcode
+    let (m, instructions) = run("0x0 0xF1FFFF\n0x1 0xF00000\n");
+    assert_eq!(m.accu(), 0xFFFFFF);
+    assert_eq!(instructions[0].opcode, 0xF1);
+}
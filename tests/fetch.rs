@@ -0,0 +1,66 @@
+//! Tests for firmwares with their own fetch phase.
+extern crate mimar;
+
+use std::cell::RefCell;
+
+use mimar::{Mima, MimaState};
+use mimar::firmware::{Firmware, Instruction, InstructionKind, OperandKind};
+use mimar::logger::Logger;
+use mimar::masks::*;
+
+/// Logger which records the addresses of the executed instructions.
+#[derive(Default)]
+struct AddressLogger {
+    addresses: RefCell<Vec<u32>>,
+}
+
+impl Logger for AddressLogger {
+    fn log_instruction(&self, _: &Mima, iar: u32, _: &Instruction, _: u32) {
+        self.addresses.borrow_mut().push(iar);
+    }
+}
+
+/// Build a firmware whose fetch phase leaves incrementing the IAR to the
+/// instructions.
+fn firmware() -> Firmware {
+    let mut firmware = Firmware::new();
+    firmware.load_memory(&[
+        // fetch, computes IAR + 1 into Z
+        IAR_WRITE | SAR_READ | X_READ | MEM_READ | 0x01,
+        ONE_WRITE | Y_READ | MEM_READ | 0x02,
+        ALU_C0 | MEM_READ | 0x03,
+        SDR_WRITE | IR_READ | 0xFF,
+        // LDC
+        IR_WRITE | ACCU_READ | 0x05,
+        Z_WRITE | IAR_READ,
+    ]);
+    for &(mnemonic, opcode, start) in &[("LDC", 0x0, 0x04), ("HALT", 0xF0, 0x00)] {
+        firmware.insert_instruction(Instruction {
+            opcode: opcode,
+            mnemonic: mnemonic.into(),
+            start: start,
+            kind: InstructionKind::from_mnemonic(mnemonic),
+            operand: OperandKind::from_opcode(opcode),
+        });
+    }
+    firmware
+}
+
+#[test]
+fn instruction_address_from_fetch_phase() {
+    let mut m = Mima::builder()
+        .firmware(firmware())
+        .set_cell(0x0, 0x000007)
+        .set_cell(0x1, 0x000008)
+        .set_cell(0x2, 0xF00000)
+        .build();
+    m.explicit_fetch = true;
+    let log = AddressLogger::default();
+    let mut state = MimaState::Running;
+    while state == MimaState::Running {
+        state = m.cycle(&log);
+    }
+    assert_eq!(state, MimaState::Halted);
+    assert_eq!(m.accu(), 8);
+    assert_eq!(log.addresses.into_inner(), vec![0x0, 0x1, 0x2]);
+}
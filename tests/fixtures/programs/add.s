@@ -0,0 +1,9 @@
+; Add two numbers and store the result in SUM.
+A:     DS 5
+B:     DS 7
+SUM:   DS 0
+       *= $100
+START: LDV A
+       ADD B
+       STV SUM
+       HALT
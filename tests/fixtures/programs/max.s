@@ -0,0 +1,16 @@
+; Store the larger of A and B in MAX, using JMN on B - A.
+A:      DS 9
+B:      DS 4
+MAX:    DS 0
+        *= $100
+START:  LDV A
+        NOT
+        ADD ONE
+        ADD B
+        JMN TAKEA
+        LDV B
+        JMP STORE
+TAKEA:  LDV A
+STORE:  STV MAX
+        HALT
+ONE:    DS 1
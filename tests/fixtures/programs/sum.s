@@ -0,0 +1,25 @@
+; Sum up the array DATA of length N into SUM.
+N = 5
+DATA:  DS 3, 1, 4, 1, 5
+PTR:   DS 0
+COUNT: DS 0
+SUM:   DS 0
+MINUS: DS $FFFFFF
+       *= $100
+START: LDC DATA
+       STV PTR
+       LDC N
+       STV COUNT
+LOOP:  LDV COUNT
+       ADD MINUS
+       JMN DONE
+       STV COUNT
+       LDIV PTR
+       ADD SUM
+       STV SUM
+       LDC 1
+       ADD PTR
+       STV PTR
+       JMP LOOP
+DONE:  LDV SUM
+       HALT
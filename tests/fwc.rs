@@ -0,0 +1,81 @@
+//! Tests for the firmware compiler.
+extern crate mimar;
+
+use mimar::firmware::{Firmware, InstructionKind};
+use mimar::fwc::{self, FwcError, RTError};
+use mimar::registers::Register;
+
+#[test]
+fn stored_default_firmware_is_up_to_date() {
+    // stock_firmware() uses the checked in src/default.mimafw
+    let compiled = fwc::compile(include_str!("../src/default-fw.txt").as_bytes()).unwrap();
+    let stored = Firmware::load(include_str!("../src/default.mimafw").as_bytes()).unwrap();
+    assert_eq!(compiled, stored);
+}
+
+#[test]
+fn decompiled_firmware_compiles_to_the_same() {
+    let firmware = fwc::compile(include_str!("../src/default-fw.txt").as_bytes()).unwrap();
+    let source = fwc::decompile_firmware(&firmware);
+    assert_eq!(fwc::compile(source.as_bytes()).unwrap(), firmware);
+}
+
+#[test]
+fn kinds_are_parsed() {
+    let firmware = fwc::compile("define HALT 0xF0 halt\n".as_bytes()).unwrap();
+    assert_eq!(firmware.find_instruction(0xF0).unwrap().kind, InstructionKind::Halt);
+}
+
+#[test]
+fn kinds_default_to_the_mnemonic() {
+    let firmware = fwc::compile("define HALT 0xF0\ndefine JMN 0x9\nIR -> IAR\n".as_bytes()).unwrap();
+    assert_eq!(firmware.find_instruction(0xF0).unwrap().kind, InstructionKind::Halt);
+    assert_eq!(firmware.find_instruction(0x9).unwrap().kind,
+               InstructionKind::ConditionalJumpNegative);
+    let firmware = fwc::compile("define HALT 0xF0 normal\nIR -> Accu\n".as_bytes()).unwrap();
+    assert_eq!(firmware.find_instruction(0xF0).unwrap().kind, InstructionKind::Normal);
+}
+
+#[test]
+fn errors_carry_the_line_number() {
+    match fwc::compile("define LDC 0x0\nIR -> Accu\n-> Accu\n".as_bytes()) {
+        Err(FwcError::ParseError(3, RTError::BusEmpty(Register::Accu), _)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    match fwc::compile("define LDC 0x0\ngoto nowhere\n".as_bytes()) {
+        Err(FwcError::UndefinedLabel(2, ref label)) => assert_eq!(label, "nowhere"),
+        other => panic!("unexpected result {:?}", other),
+    }
+    match fwc::compile("define LDC 0x0\ndefine LDV 0x0\n".as_bytes()) {
+        Err(FwcError::DuplicateOpcode(2, 0)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn explicit_fetch_phase_is_required() {
+    match fwc::compile_explicit_fetch("define LDC 0x0\nIR -> Accu\n".as_bytes()) {
+        Err(FwcError::MissingFetchPhase(1, ref mnemonic)) => assert_eq!(mnemonic, "LDC"),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn decompiling_is_independent_of_the_order() {
+    let source = "define JMN 0x9\ndefine ADD 0x3\nIR -> SAR; R = 1\nR = 1\nR = 1\nSDR -> Accu\n";
+    let firmware = fwc::compile(source.as_bytes()).unwrap();
+    let mut saved = Vec::new();
+    firmware.save(&mut saved).unwrap();
+    let loaded = Firmware::load(&saved[..]).unwrap();
+    assert_eq!(loaded.instructions()[0].mnemonic, "ADD");
+
+    let decompiled = fwc::decompile_firmware(&loaded);
+    assert!(decompiled.contains("define ADD 0x3\nIR -> SAR; R = 1\n"), "{}", decompiled);
+    assert!(decompiled.contains("define JMN 0x9\n\n"), "{}", decompiled);
+    // the start of JMN doesn't matter, as it doesn't run any code
+    let recompiled = fwc::compile(decompiled.as_bytes()).unwrap();
+    assert_eq!(recompiled.code, loaded.code);
+    assert_eq!(recompiled.find_instruction(0x3), loaded.find_instruction(0x3));
+    assert_eq!(recompiled.find_instruction(0x9).unwrap().kind,
+               InstructionKind::ConditionalJumpNegative);
+}
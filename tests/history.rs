@@ -0,0 +1,103 @@
+//! Tests for reverting cycles with the history.
+extern crate mimar;
+
+mod common;
+
+use mimar::{Mima, MimaState};
+use mimar::firmware::stock_firmware;
+use mimar::logger::NoLogging;
+
+use common::mima;
+
+#[test]
+fn revert_whole_program() {
+    let mut m = mima();
+    m.history_depth = 1000;
+    let initial = m.clone();
+    assert_eq!(m.run_fast(1000), MimaState::Halted);
+    assert_eq!(m.get_memory(0x11), 42);
+
+    while m.step_back() {}
+    assert_eq!(m.cycle_count, 0);
+    assert_eq!(m.registers, initial.registers);
+    assert_eq!(m.memory, initial.memory);
+    assert_eq!(m.next_instruction, initial.next_instruction);
+}
+
+#[test]
+fn replay_after_step_back() {
+    let mut m = mima();
+    m.history_depth = 1000;
+    while m.cycle_count < 20 {
+        m.cycle_silent();
+    }
+    let snapshot = m.clone();
+    m.run_fast(5);
+    for _ in 0..5 {
+        assert!(m.step_back());
+    }
+    assert_eq!(m.registers, snapshot.registers);
+    assert_eq!(m.memory_timer(), snapshot.memory_timer());
+
+    // Running again gives the same result as without stepping back
+    assert_eq!(m.run_fast(1000), MimaState::Halted);
+    let mut reference = mima();
+    reference.run_fast(1000);
+    assert_eq!(m.cycle_count, reference.cycle_count);
+    assert_eq!(m.registers, reference.registers);
+    assert_eq!(m.memory, reference.memory);
+}
+
+#[test]
+fn depth_is_bounded() {
+    let mut m = mima();
+    m.history_depth = 10;
+    m.run_fast(30);
+    assert_eq!(m.history_len(), 10);
+    let mut reverted = 0;
+    while m.step_back() {
+        reverted += 1;
+    }
+    assert_eq!(reverted, 10);
+    assert_eq!(m.cycle_count, 20);
+}
+
+#[test]
+fn revert_instruction() {
+    let mut m = mima();
+    m.history_depth = 1000;
+    m.step_instruction(&NoLogging);
+    let after_first = m.clone();
+    m.step_instruction(&NoLogging);
+    assert!(m.step_back_instruction());
+    assert_eq!(m.cycle_count, after_first.cycle_count);
+    assert_eq!(m.registers, after_first.registers);
+    assert_eq!(m.next_instruction, 0);
+}
+
+#[test]
+fn input_is_restored() {
+    // LDV 0x10
+    // HALT
+    let mut m = Mima::builder()
+        .firmware(stock_firmware())
+        .memory_from_str("0x0 0x100010\n0x1 0xF00000\n")
+        .unwrap()
+        .build();
+    m.set_input_address(0x10);
+    m.push_input(7);
+    m.history_depth = 1000;
+    assert_eq!(m.run_fast(1000), MimaState::Halted);
+    assert_eq!(m.accu(), 7);
+    while m.step_back() {}
+    assert_eq!(m.run_fast(1000), MimaState::Halted);
+    assert_eq!(m.accu(), 7);
+}
+
+#[test]
+fn disabled_by_default() {
+    let mut m = mima();
+    m.run_fast(10);
+    assert!(!m.step_back());
+    assert_eq!(m.cycle_count, 10);
+}
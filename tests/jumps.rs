@@ -0,0 +1,37 @@
+//! Tests for the conditional jump.
+extern crate mimar;
+
+mod common;
+
+use mimar::MimaState;
+use mimar::firmware::{Instruction, InstructionKind, OperandKind};
+use mimar::logger::NoLogging;
+use mimar::registers::Register;
+
+/// Run `JMN 0x20` with the given accumulator and return the next IAR.
+fn jmn(accu: u32) -> u32 {
+    let mut m = common::mima();
+    m.firmware.insert_instruction(Instruction {
+        opcode: 0x9,
+        mnemonic: "JMN".into(),
+        start: 0x00,
+        kind: InstructionKind::ConditionalJumpNegative,
+        operand: OperandKind::Address,
+    });
+    m.set_memory(0x0, 0x900020);
+    m.set_register(Register::Accu, accu);
+    assert_eq!(m.step_instruction(&NoLogging), MimaState::Running);
+    m.iar()
+}
+
+#[test]
+fn sign_bit_is_negative() {
+    assert_eq!(jmn(0x800000), 0x20);
+    assert_eq!(jmn(0xFFFFFF), 0x20);
+}
+
+#[test]
+fn largest_positive_is_not_negative() {
+    assert_eq!(jmn(0x7FFFFF), 0x1);
+    assert_eq!(jmn(0x0), 0x1);
+}
@@ -0,0 +1,12 @@
+//! Golden test for the Logisim export of the default firmware.
+extern crate mimar;
+
+use mimar::firmware::Firmware;
+
+#[test]
+fn default_firmware() {
+    let firmware = Firmware::load_from_str(include_str!("../src/default.mimafw")).unwrap();
+    let mut output = Vec::new();
+    firmware.save_logisim(&mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), include_str!("fixtures/default.logisim"));
+}
@@ -0,0 +1,68 @@
+//! Tests for the execution of malformed microcode.
+extern crate mimar;
+
+mod common;
+
+use mimar::{Mima, MimaError, MimaState};
+use mimar::fwc;
+use mimar::firmware::{Instruction, InstructionKind, OperandKind};
+use mimar::logger::NoLogging;
+use mimar::masks::*;
+use mimar::registers::Register;
+
+use common::mima;
+
+#[test]
+fn empty_microcode_is_an_error() {
+    let mut m = mima();
+    // LDV now starts in unpopulated microcode
+    m.firmware.insert_instruction(Instruction {
+        opcode: 0x1,
+        mnemonic: "LDV".into(),
+        start: 0x80,
+        kind: InstructionKind::Normal,
+        operand: OperandKind::Address,
+    });
+    let state = (0..100)
+        .map(|_| m.cycle(&NoLogging))
+        .find(|state| *state != MimaState::Running);
+    assert_eq!(state, Some(MimaState::Error(MimaError::EmptyMicrocode)));
+    // fetch phase, then the decode phase finds the empty start
+    assert_eq!(m.cycle_count, 6);
+}
+
+#[test]
+fn goto_only_line_runs() {
+    let source = "define LDC 0x0\nIR -> Accu; goto done\ndefine HALT 0xF0\n\
+                  define NOP 0xF5\nIR -> X\ndone: goto fetch\n";
+    let firmware = fwc::compile(source.as_bytes()).unwrap();
+    let mut m = Mima::builder()
+        .firmware(firmware)
+        .set_cell(0x0, 0x000007)
+        .set_cell(0x1, 0xF50000)
+        .set_cell(0x2, 0xF00000)
+        .build();
+    assert_eq!(m.run_fast(100), MimaState::Halted);
+    assert_eq!(m.accu(), 7);
+}
+
+#[test]
+fn single_source_multiple_targets() {
+    let mut m = Mima::new();
+    // SDR -> X; SDR -> Y; SDR -> Accu
+    m.firmware.load_memory(&[SDR_WRITE | X_READ | Y_READ | ACCU_READ | 0x01]);
+    m.set_register(Register::SDR, 42);
+    assert_eq!(m.cycle(&NoLogging), MimaState::Running);
+    assert_eq!(m.cycle_count, 1);
+    assert_eq!(m.x(), 42);
+    assert_eq!(m.y(), 42);
+    assert_eq!(m.accu(), 42);
+}
+
+#[test]
+fn multiple_sources_are_an_error() {
+    let mut m = Mima::new();
+    // Accu -> X; IR -> X
+    m.firmware.load_memory(&[ACCU_WRITE | IR_WRITE | X_READ | 0x01]);
+    assert_eq!(m.cycle(&NoLogging), MimaState::Error(MimaError::BusBusy));
+}
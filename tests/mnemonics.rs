@@ -0,0 +1,45 @@
+//! Tests for looking up instructions by mnemonic.
+extern crate mimar;
+
+mod common;
+
+use mimar::firmware::{Instruction, InstructionKind, OperandKind};
+
+use common::firmware;
+
+#[test]
+fn duplicate_mnemonic() {
+    let mut firmware = firmware();
+    // reuse the microcode of LDV
+    for &opcode in &[0x3, 0x7] {
+        firmware.insert_instruction(Instruction {
+            opcode: opcode,
+            mnemonic: "ADD".into(),
+            start: 0x05,
+            kind: InstructionKind::Normal,
+            operand: OperandKind::Address,
+        });
+    }
+    let opcodes = firmware.instructions_with_mnemonic("ADD").iter()
+        .map(|i| i.opcode)
+        .collect::<Vec<_>>();
+    assert_eq!(opcodes, vec![0x3, 0x7]);
+    // the first definition wins
+    assert_eq!(firmware.find_instruction_by_mnemonic("ADD").unwrap().opcode, 0x3);
+    assert_eq!(firmware.instructions_with_mnemonic("LDV").len(), 1);
+}
+
+#[test]
+fn only_ascii_case_is_ignored() {
+    let mut firmware = firmware();
+    firmware.insert_instruction(Instruction {
+        opcode: 0x3,
+        mnemonic: "ÄDD".into(),
+        start: 0x05,
+        kind: InstructionKind::Normal,
+        operand: OperandKind::Address,
+    });
+    assert_eq!(firmware.find_instruction_by_mnemonic("Ädd").map(|i| i.opcode), Some(0x3));
+    assert!(firmware.find_instruction_by_mnemonic("ädd").is_none());
+    assert!(firmware.instructions_with_mnemonic("ädd").is_empty());
+}
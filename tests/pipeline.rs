@@ -0,0 +1,95 @@
+//! End-to-end tests for the whole toolchain: the default firmware is compiled,
+//! the programs in `fixtures/programs` are assembled with it and run until they
+//! halt.
+extern crate mimar;
+
+mod common;
+
+use mimar::{asm, fwc};
+use mimar::{Mima, MimaState};
+use mimar::firmware::Firmware;
+
+use common::InstructionLogger;
+
+/// Compile the default firmware from its source.
+fn firmware() -> Firmware {
+    fwc::compile(include_str!("../src/default-fw.txt").as_bytes()).unwrap()
+}
+
+/// Assemble the given program, load it at `START` and run it until it halts.
+///
+/// The mnemonics of the executed instructions are returned as well.
+fn run(source: &str) -> (Mima, Vec<String>) {
+    let firmware = firmware();
+    let program = asm::assemble(&firmware, source.as_bytes()).unwrap();
+    let mut compiled = Vec::new();
+    firmware.save(&mut compiled).unwrap();
+    let mut m = Mima::load_program(&compiled[..], program.as_bytes(), Some("START")).unwrap();
+    let log = InstructionLogger::default();
+    let mut state = MimaState::Running;
+    while state == MimaState::Running && m.cycle_count < 10000 {
+        state = m.cycle(&log);
+    }
+    assert_eq!(state, MimaState::Halted);
+    let mnemonics = log.instructions.into_inner().into_iter().map(|i| i.mnemonic).collect();
+    (m, mnemonics)
+}
+
+/// Read the cell at the given label.
+fn cell(m: &Mima, label: &str) -> u32 {
+    m.get_memory(m.labels[label])
+}
+
+#[test]
+fn compiled_firmware_is_the_stock_firmware() {
+    assert_eq!(firmware(), mimar::firmware::stock_firmware());
+}
+
+#[test]
+fn add_two_numbers() {
+    let (m, mnemonics) = run(include_str!("fixtures/programs/add.s"));
+    assert_eq!(cell(&m, "SUM"), 12);
+    assert_eq!(m.accu(), 12);
+    assert_eq!(mnemonics, vec!["LDV", "ADD", "STV", "HALT"]);
+}
+
+#[test]
+fn sum_array_in_loop() {
+    let (m, mnemonics) = run(include_str!("fixtures/programs/sum.s"));
+    assert_eq!(m.accu(), 14);
+    assert_eq!(cell(&m, "SUM"), 14);
+    assert_eq!(cell(&m, "PTR"), m.labels["DATA"] + 5);
+    assert_eq!(mnemonics.iter().filter(|m| *m == "LDIV").count(), 5);
+    assert_eq!(mnemonics.iter().filter(|m| *m == "JMN").count(), 6);
+}
+
+#[test]
+fn conditional_jump() {
+    let (m, mnemonics) = run(include_str!("fixtures/programs/max.s"));
+    assert_eq!(cell(&m, "MAX"), 9);
+    assert!(!mnemonics.contains(&"JMP".to_string()));
+
+    // swap the operands, so the other branch is taken
+    let source = include_str!("fixtures/programs/max.s")
+        .replace("DS 9", "DS 2")
+        .replace("DS 4", "DS 9");
+    let (m, mnemonics) = run(&source);
+    assert_eq!(cell(&m, "MAX"), 9);
+    assert!(mnemonics.contains(&"JMP".to_string()));
+}
+
+#[test]
+fn output_with_other_whitespace_loads() {
+    let firmware = firmware();
+    let program = asm::assemble(&firmware, include_str!("fixtures/programs/sum.s").as_bytes())
+        .unwrap();
+    let mut expected = Mima::new();
+    expected.load_from_str(&program).unwrap();
+
+    let retabbed = program.replace(" ", "\t  ").replace(";", "\t; ");
+    let mut m = Mima::new();
+    m.load_from_str(&retabbed).unwrap();
+    assert_eq!(m.memory, expected.memory);
+    assert_eq!(m.labels, expected.labels);
+    assert!(!m.labels.is_empty());
+}
@@ -0,0 +1,59 @@
+//! Tests for the stock and custom registers.
+extern crate mimar;
+
+use mimar::Mima;
+use mimar::registers::{Register, RegisterDescription};
+
+fn description(width: u8) -> RegisterDescription {
+    RegisterDescription {
+        name: "R".into(),
+        width: width,
+        read_bit: None,
+        write_bit: None,
+    }
+}
+
+#[test]
+fn wide_custom_register() {
+    let mut m = Mima::new();
+    let r = m.add_register(description(32)).unwrap();
+    m.set_register(r, 0xFFFFFFFF);
+    assert_eq!(m.get_register(r), 0xFFFFFFFF);
+
+    let r = m.add_register(description(40)).unwrap();
+    m.set_register(r, 0x12345678);
+    assert_eq!(m.get_register(r), 0x12345678);
+}
+
+#[test]
+fn unknown_custom_register() {
+    let mut m = Mima::new();
+    let unknown = Register::Custom(7);
+    m.set_register(unknown, 5);
+    assert_eq!(m.get_register(unknown), 0);
+    assert!(!m.registers.contains_key(&unknown));
+    assert_eq!(m.register_name(unknown), unknown.to_string());
+    assert_eq!(m.register_width(unknown), unknown.width());
+    assert_eq!(m.register_control_bits(unknown), (None, None));
+}
+
+#[test]
+fn at_most_256_custom_registers() {
+    let mut m = Mima::new();
+    for i in 0..256 {
+        assert_eq!(m.add_register(description(24)), Some(Register::Custom(i as u8)));
+    }
+    assert_eq!(m.add_register(description(24)), None);
+    assert_eq!(m.register_name(Register::Custom(0)), "R");
+}
+
+#[test]
+fn names_round_trip() {
+    let custom = [Register::Custom(0), Register::Custom(3), Register::Custom(255)];
+    for register in Register::all().iter().chain(custom.iter()) {
+        assert_eq!(register.to_string().parse::<Register>().ok(), Some(*register));
+    }
+    assert!("Custom(256)".parse::<Register>().is_err());
+    assert!("Custom()".parse::<Register>().is_err());
+    assert!("Custom(-1)".parse::<Register>().is_err());
+}
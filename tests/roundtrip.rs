@@ -0,0 +1,91 @@
+//! Save/load round-trip tests for the firmware formats.
+extern crate mimar;
+
+use std::io::BufReader;
+
+use mimar::firmware::{Firmware, InstructionKind};
+use mimar::fwc;
+
+fn default_firmware() -> Firmware {
+    Firmware::load_from_str(include_str!("../src/default.mimafw")).unwrap()
+}
+
+#[test]
+fn text() {
+    let firmware = default_firmware();
+    let mut buffer = Vec::new();
+    firmware.save(&mut buffer).unwrap();
+    assert_eq!(Firmware::load(&buffer as &[u8]).unwrap(), firmware);
+}
+
+#[test]
+fn compact() {
+    let firmware = default_firmware();
+    let mut buffer = Vec::new();
+    firmware.save_compact(&mut buffer).unwrap();
+    assert_eq!(Firmware::load(&buffer as &[u8]).unwrap(), firmware);
+}
+
+#[test]
+fn binary() {
+    let firmware = default_firmware();
+    let mut buffer = Vec::new();
+    firmware.save_binary(&mut buffer).unwrap();
+    assert_eq!(Firmware::load(&buffer as &[u8]).unwrap(), firmware);
+}
+
+#[test]
+fn binary_with_short_reads() {
+    let firmware = default_firmware();
+    let mut buffer = Vec::new();
+    firmware.save_binary(&mut buffer).unwrap();
+    // every read only returns a single byte
+    let reader = BufReader::with_capacity(1, &buffer as &[u8]);
+    assert_eq!(Firmware::load(reader).unwrap(), firmware);
+}
+
+#[test]
+fn modified_firmware_differs() {
+    let firmware = default_firmware();
+    let mut modified = firmware.clone();
+    modified.set_memory(0xFE, 0x1);
+    assert!(modified != firmware);
+}
+
+#[test]
+fn output_is_independent_of_definition_order() {
+    let source = include_str!("../src/default.mimafw");
+    let mut lines = source.lines().collect::<Vec<_>>();
+    // move the first instruction (LDC) behind the others
+    let ldc = lines.remove(0);
+    let first_memory = lines.iter().position(|l| l.is_empty()).unwrap();
+    lines.insert(first_memory, ldc);
+    let reordered = Firmware::load_from_str(&lines.join("\n")).unwrap();
+    let firmware = default_firmware();
+    assert!(reordered.instructions()[0].opcode != firmware.instructions()[0].opcode);
+    assert_eq!(reordered, firmware);
+
+    let mut expected = Vec::new();
+    firmware.save(&mut expected).unwrap();
+    let mut output = Vec::new();
+    reordered.save(&mut output).unwrap();
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn kind_differing_from_mnemonic() {
+    let mut firmware = default_firmware();
+    let mut halt = firmware.find_instruction(0xF0).unwrap().clone();
+    halt.kind = InstructionKind::Normal;
+    firmware.insert_instruction(halt);
+
+    let mut buffer = Vec::new();
+    firmware.save(&mut buffer).unwrap();
+    assert_eq!(Firmware::load(&buffer as &[u8]).unwrap(), firmware);
+    let mut buffer = Vec::new();
+    firmware.save_binary(&mut buffer).unwrap();
+    assert_eq!(Firmware::load(&buffer as &[u8]).unwrap(), firmware);
+    let source = fwc::decompile_firmware(&firmware);
+    let compiled = fwc::compile(source.as_bytes()).unwrap();
+    assert_eq!(compiled.find_instruction(0xF0).unwrap().kind, InstructionKind::Normal);
+}
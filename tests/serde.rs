@@ -0,0 +1,68 @@
+//! Round-trip tests for the optional serde support.
+#![cfg(feature = "serde")]
+extern crate mimar;
+extern crate serde_json;
+
+mod common;
+
+use mimar::{Mima, MimaState};
+use mimar::firmware::Firmware;
+use mimar::logger::NoLogging;
+use mimar::registers::{Register, RegisterDescription};
+
+use common::mima;
+
+fn run(m: &mut Mima) -> MimaState {
+    loop {
+        let state = m.cycle(&NoLogging);
+        if state != MimaState::Running {
+            return state;
+        }
+    }
+}
+
+#[test]
+fn roundtrip_mid_execution() {
+    let mut original = mima();
+    // stop in the middle of the STV memory access
+    while original.cycle_count < 20 {
+        assert_eq!(original.cycle(&NoLogging), MimaState::Running);
+    }
+
+    let json = serde_json::to_string(&original).unwrap();
+    let mut restored: Mima = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(run(&mut original), MimaState::Halted);
+    assert_eq!(run(&mut restored), MimaState::Halted);
+    assert_eq!(original.cycle_count, restored.cycle_count);
+    assert_eq!(original.registers, restored.registers);
+    assert_eq!(original.memory, restored.memory);
+    assert_eq!(restored.get_memory(0x11), 42);
+    assert_eq!(restored.registers[&Register::Accu], 42);
+}
+
+#[test]
+fn lookup_tables_are_rebuilt() {
+    let json = serde_json::to_string(&mima().firmware).unwrap();
+    let firmware: Firmware = serde_json::from_str(&json).unwrap();
+    assert_eq!(firmware.find_instruction(0x2).unwrap().mnemonic, "STV");
+    assert_eq!(firmware.find_instruction_by_mnemonic("halt").unwrap().opcode, 0xF0);
+}
+
+#[test]
+fn roundtrip_custom_register() {
+    let mut original = mima();
+    let r0 = original.add_register(RegisterDescription {
+        name: "R0".into(),
+        width: 8,
+        read_bit: None,
+        write_bit: None,
+    }).unwrap();
+    original.set_register(r0, 0x42);
+
+    let json = serde_json::to_string(&original).unwrap();
+    let restored: Mima = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.registers, original.registers);
+    assert_eq!(restored.get_register(r0), 0x42);
+    assert_eq!(restored.register_name(r0), "R0");
+}
@@ -0,0 +1,81 @@
+//! Tests for the timing and addressing of memory accesses.
+extern crate mimar;
+
+use mimar::{Mima, MimaError, MimaState, MEMORY_ACCESS_CYCLES};
+use mimar::logger::NoLogging;
+use mimar::masks::*;
+use mimar::registers::Register;
+
+#[test]
+fn read_takes_three_cycles() {
+    let mut m = Mima::new();
+    m.firmware.load_memory(&[
+        MEM_READ | 0x01,
+        MEM_READ | 0x02,
+        MEM_READ | 0x03,
+        0x03,
+    ]);
+    m.set_register(Register::SAR, 0x10);
+    m.set_memory(0x10, 42);
+
+    assert!(!m.memory_pending());
+    for _ in 0..MEMORY_ACCESS_CYCLES {
+        assert_eq!(m.cycle(&NoLogging), MimaState::Running);
+        assert!(m.memory_pending());
+        assert_eq!(m.registers[&Register::SDR], 0);
+    }
+    assert_eq!(m.memory_timer(), 0);
+    assert_eq!(m.cycle(&NoLogging), MimaState::Running);
+    assert_eq!(m.registers[&Register::SDR], 42);
+    assert!(!m.memory_pending());
+}
+
+/// Build a MIMA that reads from an address that doesn't fit into 20 bits.
+fn wide_read() -> Mima {
+    let mut m = Mima::new();
+    m.firmware.load_memory(&[
+        MEM_READ | 0x01,
+        MEM_READ | 0x02,
+        MEM_READ | 0x03,
+        0x03,
+    ]);
+    // bypass the truncation of set_register
+    m.registers.insert(Register::SAR, 0x100010);
+    m.set_memory(0x100010, 42);
+    m
+}
+
+#[test]
+fn wide_address_lenient() {
+    let mut m = wide_read();
+    for _ in 0..MEMORY_ACCESS_CYCLES + 1 {
+        assert_eq!(m.cycle(&NoLogging), MimaState::Running);
+    }
+    assert_eq!(m.registers[&Register::SDR], 42);
+}
+
+#[test]
+fn wide_address_strict() {
+    let mut m = wide_read();
+    m.strict_memory = true;
+    for _ in 0..MEMORY_ACCESS_CYCLES {
+        assert_eq!(m.cycle(&NoLogging), MimaState::Running);
+    }
+    assert_eq!(m.cycle(&NoLogging), MimaState::Error(MimaError::MemoryAddressOutOfRange));
+}
+
+#[test]
+fn only_pure_waiting_counts_as_stall() {
+    let mut m = Mima::new();
+    m.firmware.load_memory(&[
+        MEM_READ | ONE_WRITE | ACCU_READ | 0x01,
+        MEM_READ | 0x02,
+        MEM_READ | 0x03,
+        SDR_WRITE | X_READ | 0x03,
+    ]);
+    for _ in 0..4 {
+        assert_eq!(m.cycle(&NoLogging), MimaState::Running);
+    }
+    assert_eq!(m.memory_stall_cycles(), 2);
+    assert_eq!(m.cycle_count, 4);
+}
@@ -0,0 +1,40 @@
+//! Tests for stopping at the end of the loaded program.
+extern crate mimar;
+
+use mimar::{Mima, MimaState};
+use mimar::firmware::stock_firmware;
+
+fn mima(program: &str, trap: bool) -> Mima {
+    let mut m = Mima::builder()
+        .firmware(stock_firmware())
+        .memory_from_str(program)
+        .unwrap()
+        .build();
+    m.trap_end_of_program = trap;
+    m
+}
+
+#[test]
+fn trap_after_last_instruction() {
+    // LDC 1
+    // LDC 2
+    let mut m = mima("0x0 0x000001\n0x1 0x000002\n", true);
+    assert_eq!(m.run_fast(1000), MimaState::EndOfProgram { address: 0x2 });
+    assert_eq!(m.accu(), 2);
+}
+
+#[test]
+fn empty_cells_inside_the_program_are_executed() {
+    // LDC 1
+    // DS 0
+    // HALT
+    let mut m = mima("0x0 0x000001\n0x1 0x000000\n0x2 0xF00000\n", true);
+    assert_eq!(m.run_fast(1000), MimaState::Halted);
+    assert_eq!(m.accu(), 0);
+}
+
+#[test]
+fn no_trap_by_default() {
+    let mut m = mima("0x0 0x000001\n0x1 0x000002\n", false);
+    assert_eq!(m.run_fast(1000), MimaState::Running);
+}
@@ -0,0 +1,39 @@
+//! Tests for the firmware validation.
+extern crate mimar;
+
+mod common;
+
+use mimar::firmware::{Firmware, FirmwareError, Instruction, InstructionKind, OperandKind};
+
+use common::firmware;
+
+fn with_opcode(opcode: u8) -> Firmware {
+    let mut firmware = firmware();
+    // reuse the microcode of LDV
+    firmware.insert_instruction(Instruction {
+        opcode: opcode,
+        mnemonic: "TEST".into(),
+        start: 0x05,
+        kind: InstructionKind::Normal,
+        operand: OperandKind::Address,
+    });
+    firmware
+}
+
+#[test]
+fn normal_opcode_is_valid() {
+    assert_eq!(with_opcode(0x5).validate(), Ok(()));
+}
+
+#[test]
+fn extended_opcode_is_valid() {
+    assert_eq!(with_opcode(0xF2).validate(), Ok(()));
+}
+
+#[test]
+fn ambiguous_opcodes_are_invalid() {
+    for &opcode in &[0xF, 0x10, 0x30] {
+        assert_eq!(with_opcode(opcode).validate(),
+                   Err(vec![FirmwareError::InvalidOpcode(opcode)]));
+    }
+}
@@ -0,0 +1,49 @@
+//! Tests for the warnings about suspicious behaviour.
+extern crate mimar;
+
+mod common;
+
+use std::cell::RefCell;
+
+use mimar::{Mima, MimaState};
+use mimar::logger::Logger;
+
+#[derive(Default)]
+struct WarningLogger {
+    warnings: RefCell<Vec<String>>,
+}
+
+impl Logger for WarningLogger {
+    fn log_warning(&self, _: &Mima, message: &str) {
+        self.warnings.borrow_mut().push(message.into());
+    }
+}
+
+/// Build a MIMA that overwrites its first instruction.
+fn self_modifying() -> Mima {
+    Mima::builder()
+        .firmware(common::firmware())
+        .set_cell(0x0, 0x100010)
+        .set_cell(0x1, 0x200000)
+        .set_cell(0x2, 0x200011)
+        .set_cell(0x3, 0xF00000)
+        .set_cell(0x10, 0x100010)
+        .build()
+}
+
+#[test]
+fn warns_about_self_modifying_code() {
+    let mut m = self_modifying();
+    m.warn_smc = true;
+    let log = WarningLogger::default();
+    while m.cycle(&log) == MimaState::Running {}
+    assert_eq!(*log.warnings.borrow(), vec!["self-modifying code: write to 0x00000"]);
+}
+
+#[test]
+fn no_warnings_by_default() {
+    let mut m = self_modifying();
+    let log = WarningLogger::default();
+    while m.cycle(&log) == MimaState::Running {}
+    assert!(log.warnings.borrow().is_empty());
+}